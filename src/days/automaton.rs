@@ -0,0 +1,264 @@
+use std::str::FromStr;
+
+use itertools::Itertools;
+
+use super::grid::Grid;
+
+/// The cells that decide what a cell becomes next.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Neighbourhood {
+    /// The cells up to this far to either side along a row, including the
+    /// cell itself, read left to right.
+    Window(usize),
+    /// The eight cells surrounding a cell on a grid.
+    Moore,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Comparison {
+    AtLeast,
+    AtMost,
+    Exactly,
+}
+
+/// How many of a cell's neighbours must be in some state.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Count {
+    cell: char,
+    comparison: Comparison,
+    n: usize,
+}
+
+impl Count {
+    fn holds(&self, neighbours: &[char]) -> bool {
+        let count = neighbours.iter().filter(|&&c| c == self.cell).count();
+        match self.comparison {
+            Comparison::AtLeast => count >= self.n,
+            Comparison::AtMost => count <= self.n,
+            Comparison::Exactly => count == self.n,
+        }
+    }
+}
+
+impl FromStr for Count {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        let cell = chars.next().ok_or("empty count")?;
+        let rest = chars.as_str();
+        let (comparison, n) = if let Some(n) = rest.strip_prefix(">=") {
+            (Comparison::AtLeast, n)
+        } else if let Some(n) = rest.strip_prefix("<=") {
+            (Comparison::AtMost, n)
+        } else if let Some(n) = rest.strip_prefix('=') {
+            (Comparison::Exactly, n)
+        } else {
+            return Err(format!("invalid count: {}", s));
+        };
+        let n = n
+            .parse()
+            .map_err(|e| format!("invalid count {}: {}", s, e))?;
+        Ok(Count {
+            cell,
+            comparison,
+            n,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Rule {
+    /// The cell becomes `to` when its window reads exactly `pattern`.
+    Pattern { pattern: Vec<char>, to: char },
+    /// A cell in state `from` becomes `to` when every count of its neighbours,
+    /// not including itself, holds.
+    Counts {
+        from: char,
+        counts: Vec<Count>,
+        to: char,
+    },
+    /// Any cell becomes `to`.
+    Otherwise { to: char },
+}
+
+impl Rule {
+    fn apply(&self, cell: char, window: &[char], neighbours: &[char]) -> Option<char> {
+        match self {
+            Rule::Pattern { pattern, to } => (pattern == window).then_some(*to),
+            Rule::Counts { from, counts, to } => {
+                (*from == cell && counts.iter().all(|c| c.holds(neighbours))).then_some(*to)
+            }
+            Rule::Otherwise { to } => Some(*to),
+        }
+    }
+}
+
+/// A cellular automaton described as text: a line naming the neighbourhood,
+/// `window <radius>` or `moore`, then one rule per line. Rules are tried in
+/// order and cells no rule applies to stay as they are. A rule is one of
+///
+/// - `..#.# => #`, a whole window (window neighbourhoods only),
+/// - `. |>=3 #<=1 => |`, a cell followed by counts of its neighbours, each
+///   at least (`>=`), at most (`<=`) or exactly (`=`) some number,
+/// - `* => .`, which applies to every cell.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Automaton {
+    neighbourhood: Neighbourhood,
+    rules: Vec<Rule>,
+}
+
+impl FromStr for Automaton {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().map(str::trim).filter(|line| !line.is_empty());
+        let header = lines.next().ok_or("missing neighbourhood")?;
+        let neighbourhood = match header.split_whitespace().collect_vec()[..] {
+            ["moore"] => Neighbourhood::Moore,
+            ["window", radius] => Neighbourhood::Window(
+                radius
+                    .parse()
+                    .map_err(|e| format!("invalid window radius {}: {}", radius, e))?,
+            ),
+            _ => return Err(format!("invalid neighbourhood: {}", header)),
+        };
+        let rules = lines
+            .map(|line| parse_rule(line, neighbourhood))
+            .collect::<Result<_, _>>()?;
+        Ok(Automaton {
+            neighbourhood,
+            rules,
+        })
+    }
+}
+
+fn parse_rule(s: &str, neighbourhood: Neighbourhood) -> Result<Rule, String> {
+    let (condition, to) = s
+        .split_once("=>")
+        .ok_or_else(|| format!("invalid rule: {}", s))?;
+    let to = to
+        .trim()
+        .chars()
+        .exactly_one()
+        .map_err(|_| format!("invalid rule result: {}", s))?;
+    let parts = condition.split_whitespace().collect_vec();
+    match (&parts[..], neighbourhood) {
+        (["*"], _) => Ok(Rule::Otherwise { to }),
+        ([pattern], Neighbourhood::Window(radius)) => {
+            let pattern = pattern.chars().collect_vec();
+            if pattern.len() != 2 * radius + 1 {
+                return Err(format!("rule {} should read {} cells", s, 2 * radius + 1));
+            }
+            Ok(Rule::Pattern { pattern, to })
+        }
+        ([from, counts @ ..], _) if !counts.is_empty() => Ok(Rule::Counts {
+            from: from
+                .chars()
+                .exactly_one()
+                .map_err(|_| format!("invalid rule cell: {}", s))?,
+            counts: counts.iter().map(|c| c.parse()).collect::<Result<_, _>>()?,
+            to,
+        }),
+        _ => Err(format!("invalid rule: {}", s)),
+    }
+}
+
+impl Automaton {
+    fn next_cell(&self, cell: char, window: &[char], neighbours: &[char]) -> char {
+        self.rules
+            .iter()
+            .find_map(|rule| rule.apply(cell, window, neighbours))
+            .unwrap_or(cell)
+    }
+
+    /// The next generation of a grid, where cells on the edge simply have
+    /// fewer neighbours. Windows run along rows, and a window that runs off
+    /// the grid matches no pattern.
+    pub fn step_grid(&self, grid: &Grid<char>) -> Grid<char> {
+        let mut next = grid.clone();
+        let mut window = vec![];
+        let mut neighbours = Vec::with_capacity(8);
+        for (coord, &cell) in grid.iter() {
+            window.clear();
+            neighbours.clear();
+            match self.neighbourhood {
+                Neighbourhood::Window(radius) => {
+                    let radius = radius as i32;
+                    for dx in -radius..=radius {
+                        let c = coord.offset(dx, 0);
+                        if grid.contains(c) {
+                            window.push(grid[c]);
+                            if dx != 0 {
+                                neighbours.push(grid[c]);
+                            }
+                        }
+                    }
+                    if window.len() != 2 * radius as usize + 1 {
+                        window.clear();
+                    }
+                }
+                Neighbourhood::Moore => {
+                    neighbours.extend(grid.neighbours8(coord).map(|n| grid[n]));
+                }
+            }
+            next[coord] = self.next_cell(cell, &window, &neighbours);
+        }
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window() {
+        // Rule 90: each cell becomes the exclusive or of its two neighbours.
+        let automaton = "window 1
+            ..# => #
+            #.. => #
+            ##. => #
+            .## => #
+            * => ."
+            .parse::<Automaton>()
+            .unwrap();
+        let mut grid = Grid::parse("....#....", |_, c| Ok(c)).unwrap();
+        let mut rows = vec![];
+        for _ in 0..4 {
+            rows.push(grid.render(|_, &c| c));
+            grid = automaton.step_grid(&grid);
+        }
+        assert_eq!(
+            rows,
+            vec!["....#....\n", "...#.#...\n", "..#...#..\n", ".#.#.#.#.\n"]
+        );
+    }
+
+    #[test]
+    fn test_moore() {
+        // Conway's game of life.
+        let automaton = "moore
+            # #<=1 => .
+            # #>=4 => .
+            . #=3 => #"
+            .parse::<Automaton>()
+            .unwrap();
+        let blinker = Grid::parse(".....\n..#..\n..#..\n..#..\n.....", |_, c| Ok(c)).unwrap();
+        let next = automaton.step_grid(&blinker);
+        assert_eq!(
+            next.render(|_, &c| c),
+            ".....\n.....\n.###.\n.....\n.....\n"
+        );
+        assert_eq!(automaton.step_grid(&next), blinker);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("hexagonal".parse::<Automaton>().is_err());
+        assert!("window 2\n..# => #".parse::<Automaton>().is_err());
+        assert!("moore\n..#.. => #".parse::<Automaton>().is_err());
+        assert!("moore\n. #>3 => #".parse::<Automaton>().is_err());
+        assert!("moore\n. #=3 => ##".parse::<Automaton>().is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use std::{collections::HashMap, hash::Hash};
+
+/// The states of a sequence up to the point where it starts repeating. The
+/// state at `start` comes back every `length` steps, as far as the key used to
+/// find the cycle can tell.
+#[derive(Debug)]
+pub struct Cycle<S> {
+    pub start: usize,
+    pub length: usize,
+    states: Vec<S>,
+}
+
+impl<S> Cycle<S> {
+    /// Steps from `initial` until a state's key has been seen before.
+    pub fn find<K: Hash + Eq>(
+        initial: S,
+        step: impl FnMut(&S) -> S,
+        key: impl FnMut(&S) -> K,
+    ) -> Self {
+        Self::find_within(initial, step, key, usize::MAX).unwrap()
+    }
+
+    /// Like `find`, but gives up with `None` once `bound` steps have gone by
+    /// without a repeat.
+    pub fn find_within<K: Hash + Eq>(
+        initial: S,
+        mut step: impl FnMut(&S) -> S,
+        mut key: impl FnMut(&S) -> K,
+        bound: usize,
+    ) -> Option<Self> {
+        let mut seen = HashMap::new();
+        let mut states = vec![initial];
+        loop {
+            let i = states.len() - 1;
+            if let Some(start) = seen.insert(key(&states[i]), i) {
+                return Some(Cycle {
+                    start,
+                    length: i - start,
+                    states,
+                });
+            }
+            if i == bound {
+                return None;
+            }
+            let next = step(&states[i]);
+            states.push(next);
+        }
+    }
+
+    /// Where the `n`th state falls among the states seen before the repeat,
+    /// and how many whole cycles were skipped to get there.
+    pub fn reduce(&self, n: usize) -> (usize, usize) {
+        if n < self.start {
+            (n, 0)
+        } else {
+            let n = n - self.start;
+            (self.start + n % self.length, n / self.length)
+        }
+    }
+
+    /// The state after `n` steps, or one with the same key.
+    pub fn state_at(&self, n: usize) -> &S {
+        &self.states[self.reduce(n).0]
+    }
+
+    /// The first state of the cycle and the state it repeats as one cycle
+    /// later, for working out what changes between the two.
+    pub fn ends(&self) -> (&S, &S) {
+        (
+            &self.states[self.start],
+            &self.states[self.start + self.length],
+        )
+    }
+}
+
+impl<S: Clone + Hash + Eq> Cycle<S> {
+    /// Steps from `initial` until a whole state has been seen before.
+    pub fn of(initial: S, step: impl FnMut(&S) -> S) -> Self {
+        Self::find(initial, step, S::clone)
+    }
+}
+
+/// How many differences in a row have to repeat before a sequence counts as
+/// growing linearly.
+const SETTLED: usize = 100;
+
+/// The longest period of repeating differences `Growth::find` looks for.
+const MAX_PERIOD: usize = 64;
+
+/// A sequence of values that, from `start` on, changes by the same steps
+/// every `period` values, so it grows linearly in jumps of `period`.
+#[derive(Debug)]
+pub struct Growth {
+    pub start: usize,
+    pub period: usize,
+    values: Vec<i64>,
+}
+
+impl Growth {
+    /// Reads values until the differences between them have repeated with
+    /// some period for long enough, giving up after `bound` values.
+    pub fn find(values: impl IntoIterator<Item = i64>, bound: usize) -> Result<Self, String> {
+        let mut seen = vec![];
+        // How many differences in a row matched the one a period earlier.
+        let mut runs = [0; MAX_PERIOD + 1];
+        for value in values.into_iter().take(bound) {
+            seen.push(value);
+            let j = seen.len() - 1;
+            for period in 1..=MAX_PERIOD {
+                if j > period && seen[j] - seen[j - 1] == seen[j - period] - seen[j - period - 1] {
+                    runs[period] += 1;
+                } else {
+                    runs[period] = 0;
+                }
+                if runs[period] >= SETTLED {
+                    let start = j - runs[period] - period;
+                    seen.truncate(start + period + 1);
+                    return Ok(Growth {
+                        start,
+                        period,
+                        values: seen,
+                    });
+                }
+            }
+        }
+        Err(format!(
+            "the values didn't settle into linear growth within {} values",
+            bound
+        ))
+    }
+
+    /// How much the values go up by every period.
+    pub fn step(&self) -> i64 {
+        self.values[self.start + self.period] - self.values[self.start]
+    }
+
+    /// The `n`th value, projected from the values of the first period.
+    pub fn value_at(&self, n: usize) -> i64 {
+        if n < self.start {
+            return self.values[n];
+        }
+        let n = n - self.start;
+        self.values[self.start + n % self.period] + (n / self.period) as i64 * self.step()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+
+    #[test]
+    fn test_find_cycle() {
+        let step = |&x: &u32| (x * x + 1) % 255;
+        let cycle = Cycle::of(3, step);
+        let mut x = 3;
+        let mut states = vec![x];
+        for _ in 0..1000 {
+            x = step(&x);
+            states.push(x);
+        }
+        assert_eq!(states[cycle.start], states[cycle.start + cycle.length]);
+        assert!(states[..cycle.start + cycle.length].iter().all_unique());
+        for n in [0, 1, cycle.start, cycle.start + 1, 999, 1000] {
+            assert_eq!(*cycle.state_at(n), states[n]);
+        }
+    }
+
+    #[test]
+    fn test_cycle_with_drift() {
+        // Counts up by three, but the key only sees the count modulo 4.
+        let cycle = Cycle::find(0, |&x: &u64| x + 3, |&x| x % 4);
+        assert_eq!((cycle.start, cycle.length), (0, 4));
+        let (state, cycles) = (cycle.state_at(10), cycle.reduce(10).1);
+        let (first, repeat) = cycle.ends();
+        assert_eq!(state + cycles as u64 * (repeat - first), 30);
+    }
+
+    #[test]
+    fn test_cycle_bound() {
+        let cycle = Cycle::find_within(0, |&x: &u64| (x + 1) % 10, |&x| x, 10).unwrap();
+        assert_eq!((cycle.start, cycle.length), (0, 10));
+        assert!(Cycle::find_within(0, |&x: &u64| (x + 1) % 10, |&x| x, 9).is_none());
+        assert!(Cycle::find_within(0, |&x: &u64| x + 1, |&x| x, 1000).is_none());
+    }
+
+    #[test]
+    fn test_growth() {
+        let values = (0..).map(|n: i64| if n < 10 { n * n } else { 3 * n + 5 });
+        let growth = Growth::find(values.clone(), 1000).unwrap();
+        assert_eq!((growth.start, growth.period), (10, 1));
+        for n in [0, 5, 9, 10, 11, 500] {
+            assert_eq!(growth.value_at(n), values.clone().nth(n).unwrap());
+        }
+        assert_eq!(growth.value_at(50_000_000_000), 150_000_000_005);
+
+        // Goes up by 1, 2, 4, 1, 2, 4, ... after a slow start.
+        let values = (0..).map(|n: i64| {
+            if n < 7 {
+                -n
+            } else {
+                -6 + (n - 6) / 3 * 7 + [0, 1, 3][(n - 6) as usize % 3]
+            }
+        });
+        let growth = Growth::find(values.clone(), 1000).unwrap();
+        assert_eq!((growth.start, growth.period), (6, 3));
+        for n in [0, 6, 7, 8, 9, 10, 999] {
+            assert_eq!(growth.value_at(n), values.clone().nth(n).unwrap());
+        }
+
+        assert!(Growth::find((0..).map(|n: i64| n * n), 1000).is_err());
+        assert!(Growth::find((0..).map(|n: i64| 2 * n), 50).is_err());
+    }
+}
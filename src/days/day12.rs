@@ -1,151 +1,324 @@
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr};
 
-use itertools::{FoldWhile, Itertools};
+use itertools::Itertools;
 
-use super::day::*;
+use super::{
+    cycle::{Cycle, Growth},
+    day::*,
+};
 
-pub struct Instance;
+const GENERATIONS: usize = 50_000_000_000;
+
+/// How many generations to watch for the pots repeating, or for their sum to
+/// settle into linear growth, before giving up.
+const SEARCH_BOUND: usize = 1_000;
+
+#[derive(Default)]
+pub struct Instance {
+    verbose: bool,
+}
 
 impl Day for Instance {
     fn run(&self, input: &str) -> Result<DayResult, String> {
-        let mut pot_rules = input.parse::<PotRules>()?;
-
-        let part1 = pot_rules.nth(19).unwrap().to_string();
-
-        let (num, _, diff, start) = pot_rules
-            .zip(21..)
-            .fold_while(
-                (0, 0, 0, 0),
-                |(_, count, last_diff, last_value), (value, i)| {
-                    let diff = value - last_value;
-                    if diff == last_diff {
-                        if count < 100 {
-                            FoldWhile::Continue((i, count + 1, diff, value))
-                        } else {
-                            FoldWhile::Done((i, count, diff, value))
-                        }
-                    } else {
-                        FoldWhile::Continue((i, 0, diff, value))
-                    }
-                },
-            )
-            .into_inner();
+        let pot_rules = input.parse::<PotRules>()?;
 
-        let part2 = start as i64 + (50_000_000_000 - num) * diff as i64;
+        if self.verbose {
+            for (generation, pots) in pot_rules.generations().take(21).enumerate() {
+                println!("{:2}: {}", generation, pots.render(-3, 36));
+            }
+        }
+
+        let part1 = pot_rules.sums().nth(20).unwrap().to_string();
+        // Rules that keep widening the row never repeat it, but their sum can
+        // still grow linearly.
+        let part2 = match pot_rules.cycle(SEARCH_BOUND) {
+            Some(cycle) => {
+                if self.verbose {
+                    println!(
+                        "From generation {} the pots repeat every {} generations",
+                        cycle.start, cycle.length
+                    );
+                }
+                pot_sum(&cycle, GENERATIONS)
+            }
+            None => {
+                let growth = Growth::find(pot_rules.sums(), SEARCH_BOUND)?;
+                if self.verbose {
+                    println!(
+                        "From generation {} the sum goes up by {} every {} generations",
+                        growth.start,
+                        growth.step(),
+                        growth.period
+                    );
+                }
+                growth.value_at(GENERATIONS)
+            }
+        }
+        .to_string();
 
         Ok(DayResult {
             part1,
-            part2: Some(part2.to_string()),
+            part2: Some(part2),
         })
     }
 }
 
+/// The sum of the numbers of the pots with plants after `generation`
+/// generations. Once the pattern of pots repeats it only moves along, by the
+/// same distance every cycle.
+fn pot_sum(cycle: &Cycle<Pots>, generation: usize) -> i64 {
+    let (first, repeat) = cycle.ends();
+    let drift = repeat.offset - first.offset;
+    let cycles = cycle.reduce(generation).1 as i64;
+    let pots = cycle.state_at(generation);
+    pots.sum() + pots.count() as i64 * drift * cycles
+}
+
+/// The pots with plants as a bitset, where bit `i` is the pot numbered
+/// `offset + i`. The first and last bits are always plants.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 struct Pots {
-    pots: Vec<bool>,
-    offset: i32,
+    bits: Vec<u64>,
+    len: usize,
+    offset: i64,
 }
 
-impl FromStr for Pots {
-    type Err = String;
+impl Pots {
+    fn new(plants: impl IntoIterator<Item = i64>) -> Self {
+        let mut pots = Pots {
+            bits: vec![],
+            len: 0,
+            offset: 0,
+        };
+        for plant in plants {
+            pots.push(plant);
+        }
+        pots
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let initial_state = s
-            .split_whitespace()
-            .nth(2)
-            .ok_or("missing initial state")?
-            .chars()
-            .map(|c| c == '#')
-            .collect::<Vec<_>>();
-        let offset = -4;
-        let mut pots = vec![false; 4];
-        pots.extend(initial_state);
-        pots.extend(vec![false; 4]);
-        Ok(Pots { pots, offset })
+    /// Adds a plant to the right of all the others.
+    fn push(&mut self, plant: i64) {
+        if self.len == 0 {
+            self.offset = plant;
+        }
+        let i = (plant - self.offset) as usize;
+        if self.bits.len() <= i / 64 {
+            self.bits.resize(i / 64 + 1, 0);
+        }
+        self.bits[i / 64] |= 1 << (i % 64);
+        self.len = i + 1;
     }
-}
 
-impl Pots {
-    fn next_generation(&mut self, rules: &[(Vec<bool>, bool)]) {
-        let mut next_pots = vec![false; self.pots.len() + 4];
-        for i in 2..self.pots.len() - 2 {
-            let pots = &self.pots[i - 2..=i + 2];
-            for (rule, result) in rules {
-                if pots == rule {
-                    next_pots[i + 2] = *result;
-                    break;
-                }
-            }
+    fn get(&self, pot: i64) -> bool {
+        usize::try_from(pot - self.offset)
+            .ok()
+            .filter(|&i| i < self.len)
+            .is_some_and(|i| self.bits[i / 64] & 1 << (i % 64) != 0)
+    }
+
+    /// Pots from a bitset that may start and end with empty pots, where bit
+    /// `i` is the pot numbered `offset + i`.
+    fn from_bits(bits: &[u64], offset: i64) -> Self {
+        let Some(first) = bits.iter().position(|&word| word != 0) else {
+            return Pots::new([]);
+        };
+        let last = bits.iter().rposition(|&word| word != 0).unwrap();
+        let start = first * 64 + bits[first].trailing_zeros() as usize;
+        let end = last * 64 + 64 - bits[last].leading_zeros() as usize;
+        let len = end - start;
+        let (skip, shift) = (start / 64, start % 64);
+        let bits = (skip..skip + len.div_ceil(64))
+            .map(|w| {
+                let high = match (shift, bits.get(w + 1)) {
+                    (1.., Some(&word)) => word << (64 - shift),
+                    _ => 0,
+                };
+                bits[w] >> shift | high
+            })
+            .collect();
+        Pots {
+            bits,
+            len,
+            offset: offset + start as i64,
         }
+    }
 
-        let start = next_pots
-            .windows(5)
-            .take_while(|a| a.iter().all(|t| !*t))
-            .count();
-        let end = next_pots
-            .windows(5)
-            .rev()
-            .take_while(|a| a.iter().all(|t| !*t))
-            .count();
+    /// Word `w` of the bits of the pots moved `shift` places up.
+    fn shifted(&self, shift: usize, w: usize) -> u64 {
+        let word = |w: usize| self.bits.get(w).copied().unwrap_or(0);
+        match (shift, w) {
+            (0, _) => word(w),
+            (_, 0) => word(w) << shift,
+            _ => word(w) << shift | word(w - 1) >> (64 - shift),
+        }
+    }
 
-        self.pots = next_pots[start..next_pots.len() - end].to_vec();
-        self.offset += start as i32 - 2;
+    /// Works out 64 pots at a time. Numbering the new pots from two left of
+    /// the first plant, bit `b` of the five pot window of new pot `j` is old
+    /// pot `j - b`, so each window bit of a whole word of pots is the row
+    /// shifted up by `b`. A new pot has a plant when its window matches one
+    /// of the windows set in `table`. Pots further out than two from a plant
+    /// stay empty, which the rules have to agree with.
+    fn next_generation(&self, table: u32) -> Pots {
+        let bits = (0..(self.len + 4).div_ceil(64))
+            .map(|w| {
+                let windows: [u64; 5] = std::array::from_fn(|b| self.shifted(b, w));
+                (0..32)
+                    .filter(|&window| table & 1 << window != 0)
+                    .map(|window| {
+                        (0..5).fold(!0, |matches, b| {
+                            let pots = windows[b];
+                            matches & if window & 1 << b != 0 { pots } else { !pots }
+                        })
+                    })
+                    .fold(0, |plants, matches| plants | matches)
+            })
+            .collect_vec();
+        Pots::from_bits(&bits, self.offset - 2)
     }
 
-    fn alive_pots(&self) -> i32 {
-        self.pots
-            .iter()
-            .enumerate()
-            .filter(|(_, &alive)| alive)
-            .map(|(i, _)| i as i32 + self.offset)
-            .sum()
+    fn plants(&self) -> impl Iterator<Item = i64> + '_ {
+        (0..self.len)
+            .filter(|&i| self.bits[i / 64] & 1 << (i % 64) != 0)
+            .map(|i| self.offset + i as i64)
+    }
+
+    fn count(&self) -> usize {
+        self.bits.iter().map(|b| b.count_ones() as usize).sum()
+    }
+
+    fn sum(&self) -> i64 {
+        self.plants().sum()
+    }
+
+    /// The pots from number `from` up to but not including `to`.
+    fn render(&self, from: i64, to: i64) -> String {
+        (from..to)
+            .map(|pot| if self.get(pot) { '#' } else { '.' })
+            .collect()
     }
 }
 
-fn parse_rule(s: &str) -> Result<(Vec<bool>, bool), String> {
-    let parts = s.split_whitespace().collect::<Vec<&str>>();
-    if parts.len() != 3 {
-        return Err(format!("invalid rule: {}", s));
+/// The notes as a lookup table of the next generation of a pot, indexed by
+/// the window of five pots around it read as bits, leftmost highest. Any
+/// pattern without a note leaves the pot empty.
+struct PotRules {
+    pots: Pots,
+    table: u32,
+}
+
+/// Reads a note such as `..#.# => #`, checking it covers five pots and that
+/// every pot is `#` or `.`.
+fn parse_note(s: &str) -> Result<(&str, char), String> {
+    let (pattern, result) = s
+        .split_once(" => ")
+        .ok_or_else(|| format!("invalid note: {}", s))?;
+    if pattern.chars().count() != 5 {
+        return Err(format!("note {} should cover 5 pots", s));
+    }
+    let result = result
+        .chars()
+        .exactly_one()
+        .map_err(|_| format!("note {} should give one pot", s))?;
+    match pattern
+        .chars()
+        .chain([result])
+        .find(|&c| c != '#' && c != '.')
+    {
+        Some(c) => Err(format!("invalid pot: {}", c)),
+        None => Ok((pattern, result)),
     }
-    let rule = parts[0].chars().map(|c| c == '#').collect::<Vec<_>>();
-    let result = parts[2] == "#";
-    Ok((rule, result))
 }
 
 impl FromStr for PotRules {
     type Err = String;
 
+    /// Errors name the line they were found on. Patterns without a note leave
+    /// the pot empty, so the notes that do that can be left out.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut lines = s.lines();
-        let pots = lines.next().unwrap().parse::<Pots>().unwrap();
-        let rules = lines
-            .skip(1)
-            .map(|line| parse_rule(line).unwrap())
-            .collect::<Vec<_>>();
-        Ok(PotRules { pots, rules })
+        let mut lines = s.lines().zip(1..);
+        let initial_state = lines
+            .next()
+            .and_then(|(line, _)| line.strip_prefix("initial state: "))
+            .ok_or("line 1: missing initial state")?;
+        if let Some(c) = initial_state.chars().find(|&c| c != '#' && c != '.') {
+            return Err(format!("line 1: invalid pot: {}", c));
+        }
+        let pots = Pots::new(
+            initial_state
+                .chars()
+                .zip(0..)
+                .filter(|&(c, _)| c == '#')
+                .map(|(_, i)| i),
+        );
+
+        let mut notes = HashMap::new();
+        for (line, number) in lines.filter(|(line, _)| !line.trim().is_empty()) {
+            let (pattern, result) =
+                parse_note(line.trim()).map_err(|e| format!("line {}: {}", number, e))?;
+            if pattern == "....." && result == '#' {
+                return Err(format!(
+                    "line {}: plants can't grow from no plants, they would fill every pot",
+                    number
+                ));
+            }
+            if let Some((previous, first)) = notes.insert(pattern, (result, number)) {
+                return Err(if previous == result {
+                    format!("line {}: repeats the note on line {}", number, first)
+                } else {
+                    format!("line {}: contradicts the note on line {}", number, first)
+                });
+            }
+        }
+
+        // The leftmost pot of a pattern is the highest bit of its window.
+        let table = notes
+            .iter()
+            .filter(|(_, &(result, _))| result == '#')
+            .map(|(pattern, _)| {
+                pattern
+                    .chars()
+                    .fold(0, |window, c| window << 1 | (c == '#') as u32)
+            })
+            .fold(0, |table, window| table | 1 << window);
+        Ok(PotRules { pots, table })
     }
 }
 
-struct PotRules {
-    pots: Pots,
-    rules: Vec<(Vec<bool>, bool)>,
-}
+impl PotRules {
+    /// The sum of the pots with plants in every generation, simulated one
+    /// generation after another.
+    fn sums(&self) -> impl Iterator<Item = i64> + '_ {
+        self.generations().map(|pots| pots.sum())
+    }
 
-impl Iterator for PotRules {
-    type Item = i32;
+    /// The pots in every generation, starting with the initial state.
+    fn generations(&self) -> impl Iterator<Item = Pots> + '_ {
+        std::iter::successors(Some(self.pots.clone()), |pots| {
+            Some(pots.next_generation(self.table))
+        })
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.pots.next_generation(&self.rules);
-        Some(self.pots.alive_pots())
+    /// Runs the generations until the pattern of pots repeats, wherever it
+    /// is, or `None` if it hasn't after `bound` generations.
+    fn cycle(&self, bound: usize) -> Option<Cycle<Pots>> {
+        Cycle::find_within(
+            self.pots.clone(),
+            |pots| pots.next_generation(self.table),
+            |pots| pots.bits.clone(),
+            bound,
+        )
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::days::random::Random;
 
     #[test]
     fn example() {
-        let instance = Instance;
+        let instance = Instance::default();
         let input = "initial state: #..#.#..##......###...###
 
 ...## => #
@@ -169,4 +342,201 @@ mod tests {
         };
         assert_eq!(instance.run(input), Ok(expected));
     }
+
+    const EXAMPLE: &str = "initial state: #..#.#..##......###...###
+
+...## => #
+..#.. => #
+.#... => #
+.#.#. => #
+.#.## => #
+.##.. => #
+.#### => #
+#.#.# => #
+#.### => #
+##.#. => #
+##.## => #
+###.. => #
+###.# => #
+####. => #";
+
+    #[test]
+    fn test_pots() {
+        let pot_rules = EXAMPLE.parse::<PotRules>().unwrap();
+        assert_eq!(pot_rules.table.count_ones(), 14);
+        let mut pots = pot_rules.pots.clone();
+        assert_eq!(
+            pots.render(-3, 36),
+            "...#..#.#..##......###...###..........."
+        );
+        for _ in 0..20 {
+            pots = pots.next_generation(pot_rules.table);
+        }
+        assert_eq!(
+            pots.render(-3, 36),
+            ".#....##....#####...#######....#.#..##."
+        );
+        assert_eq!(pots.sum(), 325);
+
+        let pots = Pots::new([-70, 0, 63, 64, 200]);
+        assert_eq!(pots.plants().collect_vec(), vec![-70, 0, 63, 64, 200]);
+        assert_eq!((pots.bits.len(), pots.len, pots.count()), (5, 271, 5));
+    }
+
+    #[test]
+    fn test_word_generation() {
+        // The next generation worked out one pot at a time.
+        fn slide(pots: &Pots, table: u32) -> Pots {
+            let mut window = 0;
+            Pots::new(
+                (pots.offset - 2..pots.offset + pots.len as i64 + 2).filter(|&pot| {
+                    window = (window << 1 | pots.get(pot + 2) as u32) & 0b11111;
+                    table & 1 << window != 0
+                }),
+            )
+        }
+
+        let mut random = Random::new(12);
+        for _ in 0..50 {
+            let table = random.next_u64() as u32 & !1;
+            let plants = (-100..200).filter(|_| random.below(3) == 0).collect_vec();
+            let mut pots = Pots::new(plants);
+            for _ in 0..20 {
+                let next = pots.next_generation(table);
+                assert_eq!(next, slide(&pots, table));
+                pots = next;
+            }
+        }
+    }
+
+    #[test]
+    fn test_extrapolation_matches_simulation() {
+        let pot_rules = EXAMPLE.parse::<PotRules>().unwrap();
+        let cycle = pot_rules.cycle(SEARCH_BOUND).unwrap();
+        let mut pots = pot_rules.pots.clone();
+        for generation in 1..=10_000 {
+            pots = pots.next_generation(pot_rules.table);
+            assert_eq!(pots.sum(), pot_sum(&cycle, generation));
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn simulate_millions() {
+        let pot_rules = EXAMPLE.parse::<PotRules>().unwrap();
+        let cycle = pot_rules.cycle(SEARCH_BOUND).unwrap();
+        let mut pots = pot_rules.pots.clone();
+        let start = std::time::Instant::now();
+        for _ in 0..5_000_000 {
+            pots = pots.next_generation(pot_rules.table);
+        }
+        println!("5000000 generations in {:?}", start.elapsed());
+        assert_eq!(pots.sum(), pot_sum(&cycle, 5_000_000));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse = |s: &str| s.parse::<PotRules>().err();
+        assert_eq!(
+            parse("state: #.#"),
+            Some("line 1: missing initial state".to_owned())
+        );
+        assert_eq!(
+            parse("initial state: #.o"),
+            Some("line 1: invalid pot: o".to_owned())
+        );
+        assert_eq!(
+            parse("initial state: #\n\n..#.. => #\n..#. => #"),
+            Some("line 4: note ..#. => # should cover 5 pots".to_owned())
+        );
+        assert_eq!(
+            parse("initial state: #\n\n..#.. -> #"),
+            Some("line 3: invalid note: ..#.. -> #".to_owned())
+        );
+        assert_eq!(
+            parse("initial state: #\n\n..#.. => x"),
+            Some("line 3: invalid pot: x".to_owned())
+        );
+        assert_eq!(
+            parse("initial state: #\n\n..#.. => #\n#.... => .\n..#.. => #"),
+            Some("line 5: repeats the note on line 3".to_owned())
+        );
+        assert_eq!(
+            parse("initial state: #\n\n..#.. => #\n..#.. => ."),
+            Some("line 4: contradicts the note on line 3".to_owned())
+        );
+        assert!(parse("initial state: #\n\n..... => #")
+            .unwrap()
+            .starts_with("line 3: plants can't grow"));
+    }
+
+    #[test]
+    fn test_missing_notes() {
+        let pot_rules = "initial state: #..#\n\n..#.. => #\n"
+            .parse::<PotRules>()
+            .unwrap();
+        assert_eq!(pot_rules.table, 1 << 0b00100);
+        let cycle = pot_rules.cycle(SEARCH_BOUND).unwrap();
+        assert_eq!(pot_sum(&cycle, 1000), 3);
+
+        let pot_rules = "initial state: #..#".parse::<PotRules>().unwrap();
+        assert_eq!(pot_sum(&pot_rules.cycle(SEARCH_BOUND).unwrap(), 20), 0);
+
+        let pot_rules = "initial state: #\n\n##... => #\n....# => .\n.#..# => #"
+            .parse::<PotRules>()
+            .unwrap();
+        assert_eq!(pot_rules.table, 1 << 0b11000 | 1 << 0b01001);
+    }
+
+    #[test]
+    fn test_widening() {
+        // The plants spread both ways forever, so the pots never repeat, but
+        // the sum stays the same.
+        let input = "initial state: #\n\n....# => #\n#.... => #\n..#.. => #";
+        let pot_rules = input.parse::<PotRules>().unwrap();
+        assert!(pot_rules.cycle(1000).is_none());
+        let expected = DayResult {
+            part1: "0".to_owned(),
+            part2: Some("0".to_owned()),
+        };
+        assert_eq!(Instance::default().run(input), Ok(expected));
+    }
+
+    #[test]
+    fn test_no_growth() {
+        // Each pot becomes whether exactly one of it and its right neighbour
+        // has a plant, which spreads out left in a pattern that never
+        // settles into linear growth.
+        let notes = (0..32)
+            .filter(|window| (window >> 2 ^ window >> 1) & 1 == 1)
+            .map(|window: u32| {
+                let pots = (0..5)
+                    .rev()
+                    .map(|bit| if window & 1 << bit != 0 { '#' } else { '.' })
+                    .collect::<String>();
+                format!("{} => #", pots)
+            })
+            .join("\n");
+        let input = format!("initial state: #\n\n{}", notes);
+        assert_eq!(
+            Instance::default().run(&input),
+            Err(format!(
+                "the values didn't settle into linear growth within {} values",
+                SEARCH_BOUND
+            ))
+        );
+    }
+
+    #[test]
+    fn test_growth() {
+        let pot_rules = EXAMPLE.parse::<PotRules>().unwrap();
+        let growth = Growth::find(pot_rules.sums(), 1000).unwrap();
+        assert_eq!((growth.period, growth.step()), (1, 20));
+        assert_eq!(growth.value_at(20), 325);
+        assert_eq!(
+            growth.value_at(GENERATIONS),
+            pot_sum(&pot_rules.cycle(SEARCH_BOUND).unwrap(), GENERATIONS)
+        );
+        assert!(Growth::find(pot_rules.sums(), 50).is_err());
+    }
 }
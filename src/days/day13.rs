@@ -1,17 +1,63 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{collections::HashMap, fmt, str::FromStr};
 
-use super::day::*;
+use super::{
+    day::*,
+    grid::{Coord, Grid},
+    playback::Playback,
+    search::{bfs, Control},
+};
 
-pub struct Instance;
+#[derive(Default)]
+pub struct Instance {
+    verbose: bool,
+    /// Draw every tick in the terminal at this many frames per second.
+    playback: Option<f64>,
+    /// Print the track as it is after this many ticks.
+    dump_tick: Option<usize>,
+    /// How carts behave, as read by `Rules::from_str`. Empty for the
+    /// puzzle's own rules.
+    rules: String,
+}
 
 impl Day for Instance {
-    fn run(&self, _input: &str) -> Result<DayResult, String> {
-        let mut carts = Minecarts::from_str(_input)?;
+    fn run(&self, input: &str) -> Result<DayResult, String> {
+        let carts = Minecarts::from_str(input)?.with_rules(self.rules.parse()?)?;
+        if self.verbose {
+            for piece in carts.pieces() {
+                println!(
+                    "Track piece from {},{}: {} cells, {} carts",
+                    piece.start.x, piece.start.y, piece.cells, piece.carts
+                );
+            }
+        }
+        if let Some(frames_per_second) = self.playback {
+            play(carts.clone(), frames_per_second)?;
+        }
+        if let Some(tick) = self.dump_tick {
+            print!("{}", render_tick(carts.clone(), tick));
+        }
+
+        let timeline = run_to_sole_survivor(carts);
+        if self.verbose {
+            for crash in &timeline.crashes {
+                println!("{}", crash);
+            }
+            println!("Stopped after {} ticks", timeline.ticks);
+        }
 
-        let part1 = run_to_crash(&mut carts);
+        let part1 = timeline.crashes.first().ok_or("no carts crash")?.coord;
         let part1 = format!("{},{}", part1.x, part1.y);
 
-        let part2 = run_to_sole_survivor(carts);
+        let part2 = match timeline.survivor {
+            Some(survivor) => survivor.coord,
+            None if timeline.running > 1 => {
+                return Err(format!(
+                    "{} carts still running after {} ticks",
+                    timeline.running, timeline.ticks
+                ))
+            }
+            None => return Err("no cart survives".to_owned()),
+        };
         let part2 = format!("{},{}", part2.x, part2.y);
         Ok(DayResult {
             part1,
@@ -20,13 +66,85 @@ impl Day for Instance {
     }
 }
 
-#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy)]
-struct Coord {
-    x: i32,
-    y: i32,
+/// How carts behave, so the same track can be run under different rules.
+#[derive(Debug, Clone)]
+struct Rules {
+    /// The way a cart goes at each intersection it comes to, starting over
+    /// from the first once it has used them all.
+    turns: Vec<Turn>,
+    collision: Collision,
+    /// Stop after this many ticks even if more than one cart is running.
+    tick_limit: Option<usize>,
 }
 
-#[derive(Debug)]
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            turns: vec![Turn::Left, Turn::Straight, Turn::Right],
+            collision: Collision::RemoveBoth,
+            tick_limit: None,
+        }
+    }
+}
+
+/// What happens when a cart moves onto another one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Collision {
+    /// Both carts are taken off the track.
+    RemoveBoth,
+    /// The moving cart stays where it was and both carts turn around.
+    Bounce,
+    /// Both carts stop where they are and stay on the track, where any other
+    /// cart running into them stops too.
+    Stop,
+}
+
+impl FromStr for Collision {
+    type Err = String;
+
+    /// Reads `remove`, `bounce` or `stop`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "remove" => Ok(Collision::RemoveBoth),
+            "bounce" => Ok(Collision::Bounce),
+            "stop" => Ok(Collision::Stop),
+            _ => Err(format!("invalid collision rule: {}", s)),
+        }
+    }
+}
+
+impl FromStr for Rules {
+    type Err = String;
+
+    /// Reads one rule per line, such as `turns left straight right`,
+    /// `collision bounce` or `tick limit 20`. Rules that aren't given stay as
+    /// in the puzzle.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = Rules::default();
+        for line in s.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                ["turns", ref turns @ ..] => {
+                    rules.turns = turns
+                        .iter()
+                        .map(|turn| turn.parse())
+                        .collect::<Result<_, _>>()?;
+                }
+                ["collision", collision] => rules.collision = collision.parse()?,
+                ["tick", "limit", limit] => {
+                    rules.tick_limit = Some(
+                        limit
+                            .parse()
+                            .map_err(|e| format!("invalid tick limit {}: {}", limit, e))?,
+                    );
+                }
+                _ => return Err(format!("invalid rule: {}", line)),
+            }
+        }
+        Ok(rules)
+    }
+}
+
+#[derive(Debug, Clone)]
 enum Track {
     Horizontal,
     Vertical,
@@ -35,24 +153,44 @@ enum Track {
     CurveRight,
 }
 
-#[derive(Debug)]
+impl Track {
+    fn symbol(&self) -> char {
+        match self {
+            Track::Horizontal => '-',
+            Track::Vertical => '|',
+            Track::Intersection => '+',
+            Track::CurveLeft => '\\',
+            Track::CurveRight => '/',
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 struct Cart {
+    /// Carts are numbered from 0 in reading order of where they start.
+    id: usize,
     coord: Coord,
     direction: Direction,
-    turn: Turn,
+    /// How many intersections the cart has been through.
+    intersections: usize,
+    crashed: bool,
+    stopped: bool,
 }
 
 impl Cart {
-    fn new(coord: Coord, direction: Direction) -> Self {
+    fn new(id: usize, coord: Coord, direction: Direction) -> Self {
         Self {
+            id,
             coord,
             direction,
-            turn: Turn::Left,
+            intersections: 0,
+            crashed: false,
+            stopped: false,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 enum Direction {
     Up,
     Down,
@@ -60,91 +198,403 @@ enum Direction {
     Right,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Turn {
     Left,
     Straight,
     Right,
 }
 
+impl FromStr for Turn {
+    type Err = String;
+
+    /// Reads `left`, `straight` or `right`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "left" => Ok(Turn::Left),
+            "straight" => Ok(Turn::Straight),
+            "right" => Ok(Turn::Right),
+            _ => Err(format!("invalid turn: {}", s)),
+        }
+    }
+}
+
+impl Direction {
+    const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+
+    fn from_symbol(c: char) -> Option<Self> {
+        Direction::ALL.into_iter().find(|d| d.symbol() == c)
+    }
+
+    fn bit(self) -> Exits {
+        1 << self as u8
+    }
+
+    fn turned(self, turn: Turn) -> Self {
+        match (turn, self) {
+            (Turn::Straight, d) => d,
+            (Turn::Left, Direction::Up) | (Turn::Right, Direction::Down) => Direction::Left,
+            (Turn::Left, Direction::Down) | (Turn::Right, Direction::Up) => Direction::Right,
+            (Turn::Left, Direction::Left) | (Turn::Right, Direction::Right) => Direction::Down,
+            (Turn::Left, Direction::Right) | (Turn::Right, Direction::Left) => Direction::Up,
+        }
+    }
+
+    fn opposite(self) -> Self {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+
+    fn symbol(&self) -> char {
+        match self {
+            Direction::Up => '^',
+            Direction::Down => 'v',
+            Direction::Left => '<',
+            Direction::Right => '>',
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::Left => "left",
+            Direction::Right => "right",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Two carts running into each other. The first cart is the one that moved
+/// into the second.
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Crash {
+    tick: usize,
+    coord: Coord,
+    carts: [(usize, Direction); 2],
+    /// How many carts are left running after the crash.
+    remaining: usize,
+}
+
+impl fmt::Display for Crash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [(a, a_direction), (b, b_direction)] = self.carts;
+        write!(
+            f,
+            "Tick {}: cart {} going {} hit cart {} going {} at {},{}, {} carts left",
+            self.tick, a, a_direction, b, b_direction, self.coord.x, self.coord.y, self.remaining
+        )
+    }
+}
+
+/// Every crash until at most one cart is left running or the tick limit is
+/// reached, how many ticks that took, how many carts were still running and
+/// the last one if it was the only one.
+#[derive(Debug)]
+struct Timeline {
+    crashes: Vec<Crash>,
+    ticks: usize,
+    running: usize,
+    survivor: Option<Cart>,
+}
+
+#[derive(Clone)]
 struct Minecarts {
-    tracks: HashMap<Coord, Track>,
+    tracks: Grid<Option<Track>>,
+    exits: Grid<Exits>,
     carts: Vec<Cart>,
+    ticks: usize,
+    rules: Rules,
 }
 
 impl FromStr for Minecarts {
     type Err = String;
 
+    /// Fails on anything that isn't track, a cart or a space, and on any
+    /// problem `connect` finds with the track.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut tracks = HashMap::new();
         let mut carts = Vec::new();
+        let mut tracks = Grid::parse(s, |coord, c| {
+            let track = match c {
+                '-' => Track::Horizontal,
+                '|' => Track::Vertical,
+                '+' => Track::Intersection,
+                '/' => Track::CurveRight,
+                '\\' => Track::CurveLeft,
+                ' ' => return Ok(None),
+                _ => {
+                    let direction = Direction::from_symbol(c).ok_or_else(|| {
+                        format!("invalid track at {},{}: {}", coord.x, coord.y, c)
+                    })?;
+                    carts.push(Cart::new(carts.len(), coord, direction));
+                    return Ok(None);
+                }
+            };
+            Ok(Some(track))
+        })?;
 
-        for (y, line) in s.lines().enumerate() {
-            for (x, c) in line.chars().enumerate() {
-                let coord = Coord {
-                    x: x as i32,
-                    y: y as i32,
-                };
-                match c {
-                    '-' => {
-                        tracks.insert(coord, Track::Horizontal);
-                    }
-                    '|' => {
-                        tracks.insert(coord, Track::Vertical);
-                    }
-                    '+' => {
-                        tracks.insert(coord, Track::Intersection);
-                    }
-                    '/' => {
-                        tracks.insert(coord, Track::CurveRight);
-                    }
-                    '\\' => {
-                        tracks.insert(coord, Track::CurveLeft);
-                    }
-                    '^' => {
-                        tracks.insert(coord, Track::Vertical);
-                        carts.push(Cart::new(coord, Direction::Up));
-                    }
-                    'v' => {
-                        tracks.insert(coord, Track::Vertical);
-                        carts.push(Cart::new(coord, Direction::Down));
-                    }
-                    '<' => {
-                        tracks.insert(coord, Track::Horizontal);
-                        carts.push(Cart::new(coord, Direction::Left));
-                    }
-                    '>' => {
-                        tracks.insert(coord, Track::Horizontal);
-                        carts.push(Cart::new(coord, Direction::Right));
+        let exits = connect(&mut tracks, &carts).map_err(|problems| problems.join("\n"))?;
+        Ok(Minecarts {
+            tracks,
+            exits,
+            carts,
+            ticks: 0,
+            rules: Rules::default(),
+        })
+    }
+}
+
+fn step(coord: Coord, direction: Direction) -> Coord {
+    match direction {
+        Direction::Up => coord.offset(0, -1),
+        Direction::Down => coord.offset(0, 1),
+        Direction::Left => coord.offset(-1, 0),
+        Direction::Right => coord.offset(1, 0),
+    }
+}
+
+/// The ways out of a piece of track, one bit per `Direction`.
+type Exits = u8;
+
+const ALL_EXITS: Exits = 0b1111;
+
+fn exits_of(directions: &[Direction]) -> Exits {
+    directions.iter().fold(0, |exits, d| exits | d.bit())
+}
+
+/// The ways a piece of track could lead before looking at its neighbours. A
+/// curve can be either of two corners.
+fn possible_exits(track: &Track) -> Vec<Exits> {
+    use Direction::*;
+    match track {
+        Track::Horizontal => vec![exits_of(&[Left, Right])],
+        Track::Vertical => vec![exits_of(&[Up, Down])],
+        Track::Intersection => vec![ALL_EXITS],
+        Track::CurveRight => vec![exits_of(&[Right, Down]), exits_of(&[Left, Up])],
+        Track::CurveLeft => vec![exits_of(&[Left, Down]), exits_of(&[Right, Up])],
+    }
+}
+
+fn track_with_exits(exits: Exits) -> Option<Track> {
+    [
+        Track::Horizontal,
+        Track::Vertical,
+        Track::Intersection,
+        Track::CurveRight,
+        Track::CurveLeft,
+    ]
+    .into_iter()
+    .find(|track| possible_exits(track).contains(&exits))
+}
+
+/// Works out which way every piece of track leads: which corner each curve
+/// is, from the track next to it, and which piece is hidden under each cart.
+/// Every way out of a piece has to lead into a piece that leads back, and
+/// each problem is described with its coordinates.
+fn connect(tracks: &mut Grid<Option<Track>>, carts: &[Cart]) -> Result<Grid<Exits>, Vec<String>> {
+    let mut problems = vec![];
+    let mut possible = Grid::new(tracks.bounds(), 0);
+    for (coord, track) in tracks.iter() {
+        possible[coord] = track.as_ref().map_or(0, |track| {
+            possible_exits(track).into_iter().fold(0, |a, b| a | b)
+        });
+    }
+    for cart in carts {
+        possible[cart.coord] = ALL_EXITS;
+    }
+    let leads_back = |exits: &Grid<Exits>, coord: Coord, direction: Direction| {
+        exits
+            .get(step(coord, direction))
+            .is_some_and(|&e| e & direction.opposite().bit() != 0)
+    };
+    let fits = |exits: &Grid<Exits>, coord: Coord, candidate: Exits| {
+        Direction::ALL
+            .iter()
+            .filter(|d| candidate & d.bit() != 0)
+            .all(|&d| leads_back(exits, coord, d))
+    };
+
+    let mut exits = possible.clone();
+    for (coord, track) in tracks.iter() {
+        let Some(track) = track else { continue };
+        let candidates = possible_exits(track);
+        if candidates.len() == 1 {
+            continue;
+        }
+        let fitting = candidates
+            .into_iter()
+            .filter(|&candidate| fits(&possible, coord, candidate))
+            .collect::<Vec<_>>();
+        match fitting[..] {
+            [candidate] => exits[coord] = candidate,
+            [] => problems.push(format!("curve at {},{} doesn't connect", coord.x, coord.y)),
+            _ => problems.push(format!("ambiguous corner at {},{}", coord.x, coord.y)),
+        }
+    }
+
+    // The piece under a cart has to connect to exactly the neighbours that
+    // lead back to it. A neighbouring cart whose piece isn't known yet might
+    // or might not, so carts are placed once only one piece fits, which then
+    // settles their neighbours in later rounds. Pieces the cart can move
+    // along are preferred.
+    let pieces = [
+        Track::Horizontal,
+        Track::Vertical,
+        Track::Intersection,
+        Track::CurveRight,
+        Track::CurveLeft,
+    ]
+    .iter()
+    .flat_map(possible_exits)
+    .collect::<Vec<_>>();
+    let mut unplaced = carts.iter().collect::<Vec<_>>();
+    while !unplaced.is_empty() {
+        let mut placed = vec![];
+        for (i, cart) in unplaced.iter().enumerate() {
+            let fitting = pieces
+                .iter()
+                .copied()
+                .filter(|&piece| {
+                    Direction::ALL.iter().all(|&d| {
+                        let next = step(cart.coord, d);
+                        let waiting = unplaced.iter().any(|other| other.coord == next);
+                        waiting || (piece & d.bit() != 0) == leads_back(&exits, cart.coord, d)
+                    })
+                })
+                .collect::<Vec<_>>();
+            let moving = fitting
+                .iter()
+                .copied()
+                .filter(|&piece| piece & cart.direction.bit() != 0)
+                .collect::<Vec<_>>();
+            match (&fitting[..], &moving[..]) {
+                (_, [piece]) | ([piece], []) => {
+                    exits[cart.coord] = *piece;
+                    tracks[cart.coord] = track_with_exits(*piece);
+                    if piece & cart.direction.bit() == 0 {
+                        problems.push(format!(
+                            "cart at {},{} faces off the track",
+                            cart.coord.x, cart.coord.y
+                        ));
                     }
-                    _ => {}
                 }
+                ([], _) => problems.push(format!(
+                    "no track fits under the cart at {},{}",
+                    cart.coord.x, cart.coord.y
+                )),
+                _ => continue,
             }
+            placed.push(i);
         }
+        if placed.is_empty() {
+            for cart in unplaced {
+                problems.push(format!(
+                    "ambiguous track under the cart at {},{}",
+                    cart.coord.x, cart.coord.y
+                ));
+            }
+            break;
+        }
+        for i in placed.into_iter().rev() {
+            unplaced.remove(i);
+        }
+    }
 
-        Ok(Minecarts { tracks, carts })
+    // Problems above leave pieces that don't fit, which would only show up
+    // again as dead ends.
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+    for (coord, &e) in exits.iter() {
+        for &d in Direction::ALL.iter().filter(|d| e & d.bit() != 0) {
+            if !leads_back(&exits, coord, d) {
+                problems.push(format!("dead end at {},{} going {}", coord.x, coord.y, d));
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(exits)
+    } else {
+        Err(problems)
     }
 }
 
-impl Cart {
-    fn move_tick(&mut self) {
-        match self.direction {
-            Direction::Up => {
-                self.coord.y -= 1;
-            }
-            Direction::Down => {
-                self.coord.y += 1;
-            }
-            Direction::Left => {
-                self.coord.x -= 1;
+/// A connected piece of the track network, with its first cell in reading
+/// order, how big it is and how many carts start on it.
+#[derive(Debug, PartialEq, Eq)]
+struct Piece {
+    start: Coord,
+    cells: usize,
+    carts: usize,
+}
+
+impl Minecarts {
+    fn with_rules(self, rules: Rules) -> Result<Self, String> {
+        if rules.turns.is_empty() {
+            return Err("carts need at least one turn for intersections".to_owned());
+        }
+        // Carts that bounce or stop may never get down to one running.
+        if rules.collision != Collision::RemoveBoth && rules.tick_limit.is_none() {
+            return Err("carts that stay on the track after a crash need a tick limit".to_owned());
+        }
+        Ok(Self { rules, ..self })
+    }
+
+    /// The separate pieces of track, in reading order of where they start.
+    fn pieces(&self) -> Vec<Piece> {
+        let mut seen = Grid::new(self.exits.bounds(), false);
+        let mut pieces = vec![];
+        for (start, &exits) in self.exits.iter() {
+            if exits == 0 || seen[start] {
+                continue;
             }
-            Direction::Right => {
-                self.coord.x += 1;
+            let search = bfs(
+                [start],
+                HashMap::new(),
+                |coord| {
+                    let exits = self.exits[coord];
+                    Direction::ALL
+                        .into_iter()
+                        .filter(move |d| exits & d.bit() != 0)
+                        .map(move |d| step(coord, d))
+                },
+                |_, _| Control::Continue,
+            );
+            for &coord in search.visited.keys() {
+                seen[coord] = true;
             }
+            pieces.push(Piece {
+                start,
+                cells: search.visited.len(),
+                carts: self
+                    .carts
+                    .iter()
+                    .filter(|cart| search.visited.contains_key(&cart.coord))
+                    .count(),
+            });
         }
+        pieces
+    }
+}
+
+impl Cart {
+    fn move_tick(&mut self) {
+        self.coord = step(self.coord, self.direction);
     }
 
-    fn turn(&mut self, track: &Track) {
+    fn turn(&mut self, track: &Track, turns: &[Turn]) {
         match track {
             Track::CurveLeft => {
                 self.direction = match self.direction {
@@ -162,90 +612,173 @@ impl Cart {
                     Direction::Right => Direction::Up,
                 };
             }
-            Track::Intersection => match self.turn {
-                Turn::Left => {
-                    self.direction = match self.direction {
-                        Direction::Up => Direction::Left,
-                        Direction::Down => Direction::Right,
-                        Direction::Left => Direction::Down,
-                        Direction::Right => Direction::Up,
-                    };
-                    self.turn = Turn::Straight;
-                }
-                Turn::Straight => {
-                    self.turn = Turn::Right;
-                }
-                Turn::Right => {
-                    self.direction = match self.direction {
-                        Direction::Up => Direction::Right,
-                        Direction::Down => Direction::Left,
-                        Direction::Left => Direction::Up,
-                        Direction::Right => Direction::Down,
-                    };
-                    self.turn = Turn::Left;
-                }
-            },
+            Track::Intersection => {
+                let turn = turns[self.intersections % turns.len()];
+                self.direction = self.direction.turned(turn);
+                self.intersections += 1;
+            }
             Track::Horizontal | Track::Vertical => {
                 // Do nothing
             }
         }
     }
+
+    /// Heads back the way the cart came onto the piece of track with these
+    /// exits: the other end of a curve, or straight back otherwise.
+    fn turn_around(&mut self, exits: Exits) {
+        let others = exits & !self.direction.bit();
+        let back = self.direction.opposite();
+        self.direction = if others & back.bit() != 0 {
+            back
+        } else {
+            Direction::ALL
+                .into_iter()
+                .find(|d| others & d.bit() != 0)
+                .unwrap_or(back)
+        };
+    }
+
+    fn running(&self) -> bool {
+        !self.crashed && !self.stopped
+    }
 }
 
 impl Minecarts {
-    fn tick(&mut self) -> Option<Coord> {
-        let mut first_crash = None;
+    /// Moves every running cart once, in reading order of where they are, and
+    /// returns the crashes. Crashed carts are taken off the track straight
+    /// away, so a cart that is hit before its turn doesn't move.
+    fn tick(&mut self) -> Vec<Crash> {
+        self.ticks += 1;
+        let mut crashes = Vec::new();
 
-        self.carts.sort_by_key(|cart| (cart.coord.y, cart.coord.x));
+        self.carts.sort_by_key(|cart| cart.coord);
 
-        let mut c = 0;
-        while c < self.carts.len() {
+        for c in 0..self.carts.len() {
+            if !self.carts[c].running() {
+                continue;
+            }
             let cart = &mut self.carts[c];
+            let before = cart.clone();
             cart.move_tick();
-
-            if let Some(track) = self.tracks.get(&cart.coord) {
-                cart.turn(track);
+            if let Some(Some(track)) = self.tracks.get(cart.coord) {
+                cart.turn(track, &self.rules.turns);
             }
 
             let coord = cart.coord;
-            let c_orig = c;
-            c += 1;
-            for c2 in 0..self.carts.len() {
-                if c2 != c_orig && self.carts[c2].coord == coord {
-                    if first_crash.is_none() {
-                        first_crash = Some(coord);
+            let hit = (0..self.carts.len())
+                .find(|&c2| c2 != c && !self.carts[c2].crashed && self.carts[c2].coord == coord);
+            if let Some(c2) = hit {
+                let carts = [c, c2].map(|i| (self.carts[i].id, self.carts[i].direction));
+                match self.rules.collision {
+                    Collision::RemoveBoth => {
+                        self.carts[c].crashed = true;
+                        self.carts[c2].crashed = true;
                     }
-                    self.carts.retain(|c| c.coord != coord);
-
-                    if c2 < c_orig {
-                        c -= 2;
-                    } else {
-                        c -= 1;
+                    Collision::Bounce => {
+                        let exits = self.exits[before.coord];
+                        self.carts[c] = before;
+                        self.carts[c].turn_around(exits);
+                        self.carts[c2].turn_around(self.exits[coord]);
+                    }
+                    Collision::Stop => {
+                        self.carts[c].stopped = true;
+                        self.carts[c2].stopped = true;
                     }
-
-                    break;
                 }
+                crashes.push(Crash {
+                    tick: self.ticks,
+                    coord,
+                    carts,
+                    remaining: self.running(),
+                });
             }
         }
 
-        first_crash
+        self.carts.retain(|cart| !cart.crashed);
+        crashes
+    }
+
+    /// How many carts are still moving.
+    fn running(&self) -> usize {
+        self.carts.iter().filter(|cart| cart.running()).count()
+    }
+
+    /// Whether at most one cart is left running or the tick limit is up.
+    fn finished(&self) -> bool {
+        self.running() <= 1
+            || self
+                .rules
+                .tick_limit
+                .is_some_and(|limit| self.ticks >= limit)
+    }
+
+    /// The track with the carts on it, and an `X` wherever there was one of
+    /// `crashes` or a cart has stopped.
+    fn render(&self, crashes: &[Crash]) -> String {
+        self.tracks.render(|coord, track| {
+            let cart = self.carts.iter().find(|cart| cart.coord == coord);
+            if crashes.iter().any(|crash| crash.coord == coord)
+                || cart.is_some_and(|cart| cart.stopped)
+            {
+                'X'
+            } else if let Some(cart) = cart {
+                cart.direction.symbol()
+            } else {
+                track.as_ref().map_or(' ', Track::symbol)
+            }
+        })
     }
 }
 
-fn run_to_crash(minecarts: &mut Minecarts) -> Coord {
+/// The track after `tick` ticks, or after the simulation finishes if that
+/// comes first.
+fn render_tick(mut minecarts: Minecarts, tick: usize) -> String {
+    let mut crashes = vec![];
+    while minecarts.ticks < tick && !minecarts.finished() {
+        crashes = minecarts.tick();
+    }
+    minecarts.render(&crashes)
+}
+
+/// Draws the track after every tick until the simulation finishes.
+/// Fails if `frames_per_second` isn't positive.
+fn play(mut minecarts: Minecarts, frames_per_second: f64) -> Result<(), String> {
+    let playback = Playback::new(frames_per_second)?;
+    let mut crashes = vec![];
     loop {
-        if let Some(coord) = minecarts.tick() {
-            return coord;
+        let mut frame = format!(
+            "{}Tick {}, {} carts running\n",
+            minecarts.render(&crashes),
+            minecarts.ticks,
+            minecarts.running()
+        );
+        for crash in &crashes {
+            frame += &format!("{}\n", crash);
         }
+        playback.show(&frame);
+        if minecarts.finished() {
+            return Ok(());
+        }
+        crashes = minecarts.tick();
     }
 }
 
-fn run_to_sole_survivor(mut minecarts: Minecarts) -> Coord {
-    while minecarts.carts.len() > 1 {
-        minecarts.tick();
+fn run_to_sole_survivor(mut minecarts: Minecarts) -> Timeline {
+    let mut crashes = Vec::new();
+    while !minecarts.finished() {
+        crashes.extend(minecarts.tick());
     }
 
-    minecarts.carts[0].coord
+    let running = minecarts.running();
+    Timeline {
+        crashes,
+        ticks: minecarts.ticks,
+        running,
+        survivor: minecarts
+            .carts
+            .into_iter()
+            .find(|cart| running == 1 && cart.running()),
+    }
 }
 
 #[cfg(test)]
@@ -254,7 +787,7 @@ mod tests {
 
     #[test]
     fn example() {
-        let instance = Instance;
+        let instance = Instance::default();
         let input = "/>-<\\  
 |   |  
 | /<+-\\
@@ -267,4 +800,304 @@ mod tests {
             Some("6,4".to_owned())
         );
     }
+
+    #[test]
+    fn test_first_crash() {
+        let input = r"/->-\        
+|   |  /----\
+| /-+--+-\  |
+| | |  | v  |
+\-+-/  \-+--/
+  \------/   ";
+        let timeline = run_to_sole_survivor(input.parse().unwrap());
+        assert_eq!(
+            timeline.crashes,
+            vec![Crash {
+                tick: 14,
+                coord: Coord::new(7, 3),
+                carts: [(0, Direction::Up), (1, Direction::Down)],
+                remaining: 0,
+            }]
+        );
+        assert!(timeline.survivor.is_none());
+        assert_eq!(
+            Instance::default().run(input),
+            Err("no cart survives".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_timeline() {
+        let input = r"/>-<\  
+|   |  
+| /<+-\
+| | | v
+\>+</ |
+  |   ^
+  \<->/";
+        let timeline = run_to_sole_survivor(input.parse().unwrap());
+        let crashes = timeline
+            .crashes
+            .iter()
+            .map(|crash| crash.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            crashes,
+            vec![
+                "Tick 1: cart 1 going left hit cart 0 going right at 2,0, 7 carts left",
+                "Tick 1: cart 5 going down hit cart 4 going up at 2,4, 5 carts left",
+                "Tick 1: cart 6 going up hit cart 3 going down at 6,4, 3 carts left",
+                "Tick 3: cart 7 going left hit cart 2 going right at 2,4, 1 carts left",
+            ]
+        );
+        let survivor = timeline.survivor.unwrap();
+        assert_eq!((survivor.id, survivor.coord), (8, Coord::new(6, 4)));
+        assert_eq!(timeline.ticks, 3);
+    }
+
+    #[test]
+    fn test_render() {
+        let input = r"/->-\        
+|   |  /----\
+| /-+--+-\  |
+| | |  | v  |
+\-+-/  \-+--/
+  \------/   
+";
+        assert_eq!(render_tick(input.parse().unwrap(), 0), input);
+        assert_eq!(
+            render_tick(input.parse().unwrap(), 13).lines().nth(4),
+            Some(r"\-+-/  ^-+--/")
+        );
+        let map = render_tick(input.parse().unwrap(), 14);
+        assert_eq!(map.lines().nth(3), Some("| | |  X |  |"));
+        assert_eq!(render_tick(input.parse().unwrap(), 100), map);
+    }
+
+    #[test]
+    fn test_validation() {
+        let minecarts = r"/>-<\  
+|   |  
+| /<+-\
+| | | v
+\>+</ |
+  |   ^
+  \<->/"
+            .parse::<Minecarts>()
+            .unwrap();
+        assert_eq!(
+            minecarts.pieces(),
+            vec![Piece {
+                start: Coord::new(0, 0),
+                cells: 30,
+                carts: 9
+            }]
+        );
+
+        let minecarts = "/>\\ /-\\\n^ | | |\n\\-/ \\-/"
+            .parse::<Minecarts>()
+            .unwrap();
+        assert_eq!(minecarts.pieces().len(), 2);
+        assert!(matches!(
+            minecarts.tracks[Coord::new(0, 1)],
+            Some(Track::Vertical)
+        ));
+
+        // A cart on a corner, and one on an intersection.
+        let minecarts = ">-\\\n| |\n\\-/".parse::<Minecarts>().unwrap();
+        assert!(matches!(
+            minecarts.tracks[Coord::new(0, 0)],
+            Some(Track::CurveRight)
+        ));
+        let minecarts = "  /-\\\n/-^-/\n\\-/  ".parse::<Minecarts>().unwrap();
+        assert!(matches!(
+            minecarts.tracks[Coord::new(2, 1)],
+            Some(Track::Intersection)
+        ));
+        assert_eq!(minecarts.pieces().len(), 1);
+
+        let parse = |s: &str| s.parse::<Minecarts>().err();
+        assert_eq!(
+            parse("/-\\\n| x"),
+            Some("invalid track at 2,1: x".to_owned())
+        );
+        assert_eq!(
+            parse("/->\n\\-/"),
+            Some("cart at 2,0 faces off the track".to_owned())
+        );
+        assert_eq!(
+            parse("/-\\\n|>+\n\\-/"),
+            Some("no track fits under the cart at 1,1".to_owned())
+        );
+        assert_eq!(
+            parse("-->-"),
+            Some("dead end at 0,0 going left\ndead end at 3,0 going right".to_owned())
+        );
+        assert_eq!(
+            parse(" | \n-/-\n | "),
+            Some("ambiguous corner at 1,1".to_owned())
+        );
+        // Each of these carts could be on either of two pieces until the
+        // others are known.
+        assert_eq!(
+            parse("    \n-^^-\n-v> \n  | "),
+            Some(
+                "ambiguous track under the cart at 1,1\n\
+                 ambiguous track under the cart at 2,1\n\
+                 ambiguous track under the cart at 1,2\n\
+                 ambiguous track under the cart at 2,2"
+                    .to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn test_adjacent_carts() {
+        let under = |s: &str, coords: &[(i32, i32)]| {
+            let minecarts = s.parse::<Minecarts>().unwrap();
+            coords
+                .iter()
+                .map(|&(x, y)| {
+                    minecarts.tracks[Coord::new(x, y)]
+                        .as_ref()
+                        .map(Track::symbol)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            under("/->>-\\\n\\----/", &[(2, 0), (3, 0)]),
+            vec![Some('-'), Some('-')]
+        );
+        assert_eq!(
+            under("/-\\\nv |\nv |\n\\-/", &[(0, 1), (0, 2)]),
+            vec![Some('|'), Some('|')]
+        );
+        // Carts side by side on parallel tracks, across and down.
+        assert_eq!(
+            under(
+                "/->--\\\n|/>-\\|\n||  ||\n|\\--/|\n\\----/",
+                &[(2, 0), (2, 1)]
+            ),
+            vec![Some('-'), Some('-')]
+        );
+        assert_eq!(
+            under(
+                "/----\\\n|/--\\|\nvv  ||\n||  ||\n|\\--/|\n\\----/",
+                &[(0, 2), (1, 2)]
+            ),
+            vec![Some('|'), Some('|')]
+        );
+        // Both neighbours of the first cart could be track, until the one
+        // below turns out to be on a track of its own.
+        assert_eq!(
+            under(
+                "/->>-\\\n|/>-\\|\n|\\--/|\n\\----/",
+                &[(2, 0), (3, 0), (2, 1)]
+            ),
+            vec![Some('-'), Some('-'), Some('-')]
+        );
+    }
+
+    #[test]
+    fn test_rules() {
+        let mut cart = Cart::new(0, Coord::new(0, 0), Direction::Up);
+        let turns = [Turn::Right, Turn::Right, Turn::Straight];
+        let directions = (0..4)
+            .map(|_| {
+                cart.turn(&Track::Intersection, &turns);
+                cart.direction
+            })
+            .collect::<Vec<_>>();
+        use Direction::*;
+        assert_eq!(directions, vec![Right, Down, Down, Left]);
+
+        let rules = "turns right straight\n collision stop\n tick limit 9"
+            .parse::<Rules>()
+            .unwrap();
+        assert_eq!(rules.turns, vec![Turn::Right, Turn::Straight]);
+        assert_eq!(rules.collision, Collision::Stop);
+        assert_eq!(rules.tick_limit, Some(9));
+        assert_eq!(
+            "collision crash".parse::<Rules>().unwrap_err(),
+            "invalid collision rule: crash"
+        );
+        assert_eq!(
+            "turns left back".parse::<Rules>().unwrap_err(),
+            "invalid turn: back"
+        );
+        assert_eq!(
+            "speed 2".parse::<Rules>().unwrap_err(),
+            "invalid rule: speed 2"
+        );
+
+        let input = "/->-<-\\\n\\-<---/";
+        let minecarts = |rules: &str| {
+            input
+                .parse::<Minecarts>()
+                .unwrap()
+                .with_rules(rules.parse()?)
+        };
+        let crash = "Tick 1: cart 1 going left hit cart 0 going right at 3,0";
+
+        let timeline = run_to_sole_survivor(minecarts("").unwrap());
+        assert_eq!(
+            timeline.crashes[0].to_string(),
+            format!("{}, 1 carts left", crash)
+        );
+        let survivor = timeline.survivor.unwrap();
+        assert_eq!((survivor.id, survivor.coord), (2, Coord::new(1, 1)));
+
+        let mut bouncing = minecarts("collision bounce\ntick limit 100").unwrap();
+        let crashes = bouncing.tick();
+        assert_eq!(crashes[0].to_string(), format!("{}, 3 carts left", crash));
+        bouncing.tick();
+        let carts = bouncing
+            .carts
+            .iter()
+            .map(|cart| (cart.id, cart.coord, cart.direction))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            carts,
+            vec![
+                (0, Coord::new(2, 0), Left),
+                (1, Coord::new(5, 0), Right),
+                (2, Coord::new(0, 1), Up),
+            ]
+        );
+
+        let mut stopping = minecarts("collision stop\ntick limit 100").unwrap();
+        for _ in 0..6 {
+            stopping.tick();
+        }
+        assert_eq!(stopping.running(), 0);
+        assert_eq!(stopping.render(&[]), "/--X--\\\n\\-----/\n");
+        let timeline = run_to_sole_survivor(minecarts("collision stop\ntick limit 100").unwrap());
+        assert_eq!((timeline.ticks, timeline.running), (1, 1));
+
+        let instance = Instance {
+            rules: "collision bounce\ntick limit 20".to_owned(),
+            ..Instance::default()
+        };
+        assert_eq!(
+            instance.run(input),
+            Err("3 carts still running after 20 ticks".to_owned())
+        );
+        assert!(minecarts("turns").is_err());
+        assert_eq!(
+            minecarts("collision bounce").err(),
+            Some("carts that stay on the track after a crash need a tick limit".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_playback() {
+        let instance = Instance {
+            playback: Some(-1.0),
+            ..Instance::default()
+        };
+        assert_eq!(
+            instance.run("/->-<-\\\n\\-----/"),
+            Err("invalid frames per second: -1".to_owned())
+        );
+    }
 }
@@ -1,14 +1,31 @@
-use std::{collections::HashMap, str::FromStr};
-
-use super::day::*;
-
-pub struct Instance;
+use std::{ops::RangeInclusive, str::FromStr};
+
+use super::{automaton::Automaton, cycle::Cycle, day::*, grid::Grid, playback::Playback};
+
+/// Open acres (`.`) grow trees (`|`) next to three or more trees, trees become
+/// lumberyards (`#`) next to three or more lumberyards, and lumberyards stay
+/// only while next to both trees and another lumberyard.
+const RULES: &str = "moore
+. |>=3 => |
+| #>=3 => #
+# |=0 => .
+# #=0 => .";
+
+#[derive(Default)]
+pub struct Instance {
+    animation: Option<Animation>,
+}
 
 impl Day for Instance {
     fn run(&self, input: &str) -> Result<DayResult, String> {
-        let mut lumber_collection = input.parse::<LumberCollection>()?;
-        let part1 = lumber_collection.nth(9).unwrap().to_string();
-        let part2 = part2(input).to_string();
+        let automaton = RULES.parse::<Automaton>()?;
+        let lumber_collection = input.parse::<LumberCollection>()?;
+        let cycle = lumber_collection.cycle(&automaton);
+        if let Some(animation) = &self.animation {
+            animation.play(&cycle)?;
+        }
+        let part1 = cycle.state_at(10).resouce_value().to_string();
+        let part2 = cycle.state_at(1_000_000_000).resouce_value().to_string();
         Ok(DayResult {
             part1,
             part2: Some(part2),
@@ -16,146 +33,96 @@ impl Day for Instance {
     }
 }
 
-fn part2(input: &str) -> usize {
-    let lumber_collection = input.parse::<LumberCollection>().unwrap();
-    let mut seen = HashMap::new();
-    let mut cycle = None;
-    for (i, value) in lumber_collection.enumerate() {
-        if let Some((j, prev_cyle)) = seen.get(&value) {
-            if let Some(size) = prev_cyle {
-                if i - j == *size {
-                    cycle = Some((*j, i));
-                    break;
-                }
-            }
-            seen.insert(value, (i, Some(i - j)));
-        } else {
-            seen.insert(value, (i, None));
-        }
-    }
+/// Draws the lumber collection in the terminal minute by minute.
+struct Animation {
+    frames_per_second: f64,
+    minutes: usize,
+    /// Stop at the first minute that repeats an earlier one, instead of
+    /// going on to `minutes`.
+    stop_on_cycle: bool,
+}
 
-    let (start, end) = cycle.unwrap();
-    let cycle_length = end - start;
-    let remaining = (1_000_000_000 - start) % cycle_length;
-    let mut lumber_collection = input.parse::<LumberCollection>().unwrap();
-    for _ in 0..start + remaining {
-        lumber_collection.step();
+impl Default for Animation {
+    fn default() -> Self {
+        Self {
+            frames_per_second: 10.0,
+            minutes: 1000,
+            stop_on_cycle: true,
+        }
     }
-    lumber_collection.resouce_value()
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum Acre {
-    Open,
-    Trees,
-    Lumberyard,
-}
+impl Animation {
+    fn minutes(&self, cycle: &Cycle<LumberCollection>) -> RangeInclusive<usize> {
+        if self.stop_on_cycle {
+            0..=self.minutes.min(cycle.start + cycle.length)
+        } else {
+            0..=self.minutes
+        }
+    }
 
-#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy)]
-struct Coord {
-    x: i32,
-    y: i32,
-}
+    fn frame(&self, cycle: &Cycle<LumberCollection>, minute: usize) -> String {
+        let lumber_collection = cycle.state_at(minute);
+        let mut frame = format!(
+            "Minute {}, resource value {}",
+            minute,
+            lumber_collection.resouce_value()
+        );
+        if minute >= cycle.start + cycle.length {
+            let (repeated, _) = cycle.reduce(minute - cycle.length);
+            frame += &format!(", repeating minute {}", repeated);
+        }
+        frame.push('\n');
+        frame + &lumber_collection.render_coloured()
+    }
 
-impl Coord {
-    fn neighbours(&self) -> Vec<Coord> {
-        vec![
-            Coord {
-                x: self.x - 1,
-                y: self.y - 1,
-            },
-            Coord {
-                x: self.x,
-                y: self.y - 1,
-            },
-            Coord {
-                x: self.x + 1,
-                y: self.y - 1,
-            },
-            Coord {
-                x: self.x - 1,
-                y: self.y,
-            },
-            Coord {
-                x: self.x + 1,
-                y: self.y,
-            },
-            Coord {
-                x: self.x - 1,
-                y: self.y + 1,
-            },
-            Coord {
-                x: self.x,
-                y: self.y + 1,
-            },
-            Coord {
-                x: self.x + 1,
-                y: self.y + 1,
-            },
-        ]
+    /// Fails if `frames_per_second` isn't positive.
+    fn play(&self, cycle: &Cycle<LumberCollection>) -> Result<(), String> {
+        let playback = Playback::new(self.frames_per_second)?;
+        for minute in self.minutes(cycle) {
+            playback.show(&self.frame(cycle, minute));
+        }
+        Ok(())
     }
 }
 
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 struct LumberCollection {
-    grid: HashMap<Coord, Acre>,
+    grid: Grid<char>,
 }
 
-impl Iterator for LumberCollection {
-    type Item = usize;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        self.step();
-        Some(self.resouce_value())
+impl LumberCollection {
+    /// Every state up to the first one that repeats, which can look up any
+    /// later minute without simulating it.
+    fn cycle(self, automaton: &Automaton) -> Cycle<Self> {
+        Cycle::of(self, |lumber_collection| {
+            lumber_collection.next_minute(automaton)
+        })
     }
-}
 
-impl LumberCollection {
-    fn step(&mut self) {
-        let mut new_grid = self.grid.clone();
-
-        for (coord, acre) in &self.grid {
-            let trees = coord
-                .neighbours()
-                .iter()
-                .filter_map(|n| self.grid.get(n))
-                .filter(|a| **a == Acre::Trees)
-                .count();
-            let lumberyards = coord
-                .neighbours()
-                .iter()
-                .filter_map(|n| self.grid.get(n))
-                .filter(|a| **a == Acre::Lumberyard)
-                .count();
-
-            match acre {
-                Acre::Open => {
-                    if trees >= 3 {
-                        new_grid.insert(*coord, Acre::Trees);
-                    }
-                }
-                Acre::Trees => {
-                    if lumberyards >= 3 {
-                        new_grid.insert(*coord, Acre::Lumberyard);
-                    }
-                }
-                Acre::Lumberyard => {
-                    if lumberyards == 0 || trees == 0 {
-                        new_grid.insert(*coord, Acre::Open);
-                    }
-                }
-            }
+    fn next_minute(&self, automaton: &Automaton) -> Self {
+        Self {
+            grid: automaton.step_grid(&self.grid),
         }
+    }
 
-        self.grid = new_grid;
+    /// The acres as terminal text, with trees in green and lumberyards in
+    /// yellow.
+    fn render_coloured(&self) -> String {
+        self.grid
+            .render(|_, &c| c)
+            .chars()
+            .map(|c| match c {
+                '|' => "\x1b[32m|\x1b[0m".to_owned(),
+                '#' => "\x1b[33m#\x1b[0m".to_owned(),
+                c => c.to_string(),
+            })
+            .collect()
     }
 
     fn resouce_value(&self) -> usize {
-        let trees = self.grid.values().filter(|a| **a == Acre::Trees).count();
-        let lumberyards = self
-            .grid
-            .values()
-            .filter(|a| **a == Acre::Lumberyard)
-            .count();
+        let trees = self.grid.values().filter(|&&a| a == '|').count();
+        let lumberyards = self.grid.values().filter(|&&a| a == '#').count();
         trees * lumberyards
     }
 }
@@ -164,25 +131,10 @@ impl FromStr for LumberCollection {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let grid = s
-            .lines()
-            .enumerate()
-            .flat_map(|(y, line)| {
-                line.chars().enumerate().map(move |(x, c)| {
-                    let coord = Coord {
-                        x: x as i32,
-                        y: y as i32,
-                    };
-                    let acre = match c {
-                        '.' => Acre::Open,
-                        '|' => Acre::Trees,
-                        '#' => Acre::Lumberyard,
-                        _ => return Err(format!("invalid acre: {}", c)),
-                    };
-                    Ok((coord, acre))
-                })
-            })
-            .collect::<Result<_, _>>()?;
+        let grid = Grid::parse(s, |_, c| match c {
+            '.' | '|' | '#' => Ok(c),
+            _ => Err(format!("invalid acre: {}", c)),
+        })?;
         Ok(Self { grid })
     }
 }
@@ -193,7 +145,7 @@ mod tests {
 
     #[test]
     fn example() {
-        let instance = Instance;
+        let instance = Instance::default();
         let input = ".#.#...|#.
 .....#|##|
 .|..|...#.
@@ -210,4 +162,72 @@ mod tests {
         };
         assert_eq!(instance.run(input), Ok(expected));
     }
+
+    #[test]
+    fn test_cycle_on_states() {
+        let a = "|#\n..".parse::<LumberCollection>().unwrap();
+        let b = "#|\n..".parse::<LumberCollection>().unwrap();
+        assert_eq!(a.resouce_value(), b.resouce_value());
+        assert_ne!(a, b);
+
+        let input = "#.#...#|
+..|#|.|.
+##...|..
+.#.#.#||
+|...##.|
+##|.||.|
+|#|||.#|
+|##|.|#|";
+        let automaton = RULES.parse::<Automaton>().unwrap();
+        let cycle = input.parse::<LumberCollection>().unwrap().cycle(&automaton);
+        assert_eq!((cycle.start, cycle.length), (13, 36));
+        let n = cycle.start + cycle.length + 1;
+        let mut lumber_collection = input.parse::<LumberCollection>().unwrap();
+        for _ in 0..n {
+            lumber_collection = lumber_collection.next_minute(&automaton);
+        }
+        assert_eq!(cycle.state_at(n), &lumber_collection);
+        assert_eq!(cycle.state_at(n + 1000 * cycle.length), &lumber_collection);
+    }
+
+    #[test]
+    fn test_animation() {
+        let automaton = RULES.parse::<Automaton>().unwrap();
+        let cycle = "|.\n#|"
+            .parse::<LumberCollection>()
+            .unwrap()
+            .cycle(&automaton);
+        assert_eq!((cycle.start, cycle.length), (1, 1));
+
+        let animation = Animation::default();
+        assert_eq!(animation.minutes(&cycle), 0..=2);
+        assert_eq!(
+            animation.frame(&cycle, 0),
+            "Minute 0, resource value 2
+\x1b[32m|\x1b[0m.
+\x1b[33m#\x1b[0m\x1b[32m|\x1b[0m
+"
+        );
+        assert!(animation
+            .frame(&cycle, 2)
+            .starts_with("Minute 2, resource value 0, repeating minute 1\n"));
+
+        let animation = Animation {
+            minutes: 5,
+            stop_on_cycle: false,
+            ..Animation::default()
+        };
+        assert_eq!(animation.minutes(&cycle), 0..=5);
+
+        let instance = Instance {
+            animation: Some(Animation {
+                frames_per_second: 0.0,
+                ..Animation::default()
+            }),
+        };
+        assert_eq!(
+            instance.run("|.\n#|"),
+            Err("invalid frames per second: 0".to_owned())
+        );
+    }
 }
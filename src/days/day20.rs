@@ -1,14 +1,37 @@
-use std::{collections::HashMap, iter, str::FromStr};
+use std::{fmt, path::PathBuf, str::FromStr};
 
-use super::day::*;
+use super::{
+    day::*,
+    grid::{Bounds, Coord, Grid},
+    image::Image,
+    search::{bfs, Control, Visit, Visited},
+};
+
+/// How many doors away from the start a room has to be to count as far.
+const DEFAULT_THRESHOLD: usize = 1000;
 
 pub struct Instance {
     verbose: bool,
+    /// Save a picture of the rooms here.
+    image: Option<PathBuf>,
+    /// Print the shortest route regex that opens the same doors.
+    minimal_regex: bool,
+    /// Count the rooms at least this many doors from the start for part 2.
+    threshold: usize,
+    /// Print the shortest way between each of these pairs of rooms, where the
+    /// starting room is at 0,0.
+    routes: Vec<(Coord, Coord)>,
 }
 
 impl Default for Instance {
     fn default() -> Self {
-        Self { verbose: false }
+        Self {
+            verbose: false,
+            image: None,
+            minimal_regex: false,
+            threshold: DEFAULT_THRESHOLD,
+            routes: vec![],
+        }
     }
 }
 
@@ -19,8 +42,30 @@ impl Day for Instance {
         if self.verbose {
             rooms.print();
         }
+        if let Some(path) = &self.image {
+            rooms.image().save(path)?;
+        }
+        if self.minimal_regex {
+            println!("{}", rooms.to_regex()?);
+        }
+        if self.verbose {
+            for (distance, count) in rooms.histogram().iter().enumerate() {
+                println!("{} doors away: {} rooms", distance, count);
+            }
+        }
+        for &(from, to) in &self.routes {
+            let route = match rooms.route(from, to) {
+                Some(route) => format!(
+                    "{} doors, {}",
+                    route.len(),
+                    route.iter().map(|d| d.symbol()).collect::<String>()
+                ),
+                None => "no route".to_owned(),
+            };
+            println!("From {},{} to {},{}: {}", from.x, from.y, to.x, to.y, route);
+        }
 
-        let (part1, part2) = rooms.furthest_rooms();
+        let (part1, part2) = rooms.furthest_rooms(self.threshold);
         Ok(DayResult {
             part1: part1.to_string(),
             part2: Some(part2.to_string()),
@@ -28,7 +73,7 @@ impl Day for Instance {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 enum Direction {
     North,
     East,
@@ -36,6 +81,17 @@ enum Direction {
     West,
 }
 
+impl Direction {
+    fn symbol(self) -> char {
+        match self {
+            Direction::North => 'N',
+            Direction::East => 'E',
+            Direction::South => 'S',
+            Direction::West => 'W',
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 enum RoomRegex {
     Literal(Direction),
@@ -47,10 +103,9 @@ impl FromStr for RoomRegex {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut chars = s.chars();
         let mut stack = vec![];
         let mut current = RoomRegex::Group(vec![]);
-        while let Some(c) = chars.next() {
+        for c in s.chars() {
             // println!("{:?} {:?}", c, current);
             match c {
                 '^' => {}
@@ -61,14 +116,10 @@ impl FromStr for RoomRegex {
                 }
                 ')' => {
                     let prev = current;
-                    current = stack.pop().unwrap();
+                    current = stack.pop().ok_or("unmatched )")?;
                     match current {
                         RoomRegex::Group(ref mut group) => group.push(prev),
-                        RoomRegex::Or(ref mut group) => match prev {
-                            RoomRegex::Group(g) => group.push(g),
-                            RoomRegex::Or(_) => group.last_mut().unwrap().push(prev),
-                            _ => unreachable!(),
-                        },
+                        RoomRegex::Or(ref mut group) => group.last_mut().unwrap().push(prev),
                         _ => unreachable!(),
                     }
                 }
@@ -99,10 +150,43 @@ impl FromStr for RoomRegex {
                 }
             }
         }
+        if !stack.is_empty() {
+            return Err("unmatched (".to_owned());
+        }
         Ok(current)
     }
 }
 
+impl RoomRegex {
+    /// Writes the regex without the `^` and `$`, leaving out the brackets of
+    /// groups without any alternatives.
+    fn write_route(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoomRegex::Literal(d) => write!(f, "{}", d.symbol()),
+            RoomRegex::Group(group) => group.iter().try_for_each(|r| r.write_route(f)),
+            RoomRegex::Or(branches) => {
+                write!(f, "(")?;
+                for (i, branch) in branches.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "|")?;
+                    }
+                    branch.iter().try_for_each(|r| r.write_route(f))?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl fmt::Display for RoomRegex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "^")?;
+        self.write_route(f)?;
+        write!(f, "$")
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
 struct Doors {
     north: bool,
     east: bool,
@@ -110,148 +194,287 @@ struct Doors {
     west: bool,
 }
 
-impl Default for Doors {
-    fn default() -> Self {
-        Doors {
-            north: false,
-            east: false,
-            south: false,
-            west: false,
-        }
-    }
-}
-
+#[derive(Debug, PartialEq, Eq)]
 struct Rooms {
-    rooms: HashMap<(i32, i32), Doors>,
+    rooms: Grid<Doors>,
 }
 
 impl Rooms {
-    fn print(&self) {
-        let min_x = self.rooms.keys().map(|(x, _)| x).min().copied().unwrap();
-        let max_x = self.rooms.keys().map(|(x, _)| x).max().copied().unwrap();
-        let min_y = self.rooms.keys().map(|(_, y)| y).min().copied().unwrap();
-        let max_y = self.rooms.keys().map(|(_, y)| y).max().copied().unwrap();
-        let empty = Doors::default();
-        for y in min_y..=max_y {
-            for x in min_x..=max_x {
-                let doors = self.rooms.get(&(x, y)).unwrap_or(&empty);
-                print!("#{}", if doors.north { "-" } else { "#" });
+    /// The rooms drawn with walls (`#`) around them, doors (`|` and `-`)
+    /// between them, and the starting room as `X`.
+    fn map(&self) -> Grid<char> {
+        let bounds = self.rooms.bounds();
+        let mut map = Grid::new(
+            Bounds::new(
+                Coord::new(0, 0),
+                Coord::new(2 * bounds.width() as i32, 2 * bounds.height() as i32),
+            ),
+            '#',
+        );
+        for (coord, doors) in self.rooms.iter() {
+            let room = self.map_coord(coord);
+            map[room] = if coord == Coord::new(0, 0) { 'X' } else { '.' };
+            if doors.north {
+                map[room.offset(0, -1)] = '-';
             }
-            println!("#");
-            for x in min_x..=max_x {
-                let doors = self.rooms.get(&(x, y)).unwrap_or(&empty);
-                print!(
-                    "{}{}",
-                    if doors.west { "|" } else { "#" },
-                    if x == 0 && y == 0 { "X" } else { "." }
-                );
+            if doors.west {
+                map[room.offset(-1, 0)] = '|';
             }
-            println!("#");
         }
+        map
+    }
 
-        println!(
-            "{}",
-            iter::repeat("#")
-                .take((2 * (max_x - min_x) + 3) as usize)
-                .collect::<String>()
-        );
+    /// Where a room is drawn on the map.
+    fn map_coord(&self, coord: Coord) -> Coord {
+        let min = self.rooms.bounds().min;
+        Coord::new(2 * (coord.x - min.x) + 1, 2 * (coord.y - min.y) + 1)
     }
 
-    fn furthest_rooms(&self) -> (usize, usize) {
-        let mut distances = HashMap::new();
-        distances.insert((0, 0), 0);
-        let mut stack = vec![(0, 0)];
-        while let Some((x, y)) = stack.pop() {
-            let distance = distances[&(x, y)];
-            let doors = &self.rooms[&(x, y)];
-            if doors.north {
-                let y_ = y - 1;
-                if !distances.contains_key(&(x, y_)) {
-                    distances.insert((x, y_), distance + 1);
-                    stack.push((x, y_));
-                }
+    fn print(&self) {
+        print!("{}", self.map().render(|_, &c| c));
+    }
+
+    /// The map as a picture, four pixels to a square, with walls in dark
+    /// grey, rooms in white, doors in light grey, the starting room in green
+    /// and the rooms furthest from it in red.
+    fn image(&self) -> Image {
+        let mut map = self.map();
+        let distances = self.distances();
+        let furthest = distances.values().flatten().max().copied();
+        for (coord, &distance) in distances.iter() {
+            if distance.is_some_and(|d| d > 0) && distance == furthest {
+                map[self.map_coord(coord)] = '*';
             }
-            if doors.east {
-                let x_ = x + 1;
-                if !distances.contains_key(&(x_, y)) {
-                    distances.insert((x_, y), distance + 1);
-                    stack.push((x_, y));
+        }
+        Image::from_grid(&map, 4, |_, &c| match c {
+            '#' => [0x40, 0x40, 0x40],
+            '|' | '-' => [0xc0, 0xc0, 0xc0],
+            'X' => [0x20, 0xa0, 0x20],
+            '*' => [0xd0, 0x20, 0x20],
+            _ => [0xff, 0xff, 0xff],
+        })
+    }
+
+    /// The doors out of a room, with the rooms they lead to.
+    fn doors(&self, coord: Coord) -> impl Iterator<Item = (Direction, Coord)> {
+        let doors = &self.rooms[coord];
+        [
+            (doors.north, Direction::North, coord.offset(0, -1)),
+            (doors.east, Direction::East, coord.offset(1, 0)),
+            (doors.south, Direction::South, coord.offset(0, 1)),
+            (doors.west, Direction::West, coord.offset(-1, 0)),
+        ]
+        .into_iter()
+        .filter_map(|(door, direction, next)| door.then_some((direction, next)))
+    }
+
+    /// How each room is first reached from the starting room, going through
+    /// as few doors as possible.
+    fn search(&self) -> Grid<Option<Visit<Coord, usize>>> {
+        self.search_from(Coord::new(0, 0), None)
+    }
+
+    /// How each room is first reached from `from`, stopping once `to` is
+    /// reached if it is given.
+    fn search_from(&self, from: Coord, to: Option<Coord>) -> Grid<Option<Visit<Coord, usize>>> {
+        bfs(
+            [from],
+            Grid::new(self.rooms.bounds(), None),
+            |coord| self.doors(coord).map(|(_, next)| next),
+            |coord, _| {
+                if Some(coord) == to {
+                    Control::Stop
+                } else {
+                    Control::Continue
                 }
+            },
+        )
+        .visited
+    }
+
+    /// Whether there is a room at `coord`. Every room but the starting one
+    /// was reached through a door, and squares of the map without any doors
+    /// are solid wall.
+    fn is_room(&self, coord: Coord) -> bool {
+        coord == Coord::new(0, 0)
+            || self
+                .rooms
+                .get(coord)
+                .is_some_and(|doors| *doors != Doors::default())
+    }
+
+    /// The doors to go through, in order, on a shortest way from one room to
+    /// another, or `None` if either isn't a room or there is no way through.
+    fn route(&self, from: Coord, to: Coord) -> Option<Vec<Direction>> {
+        if !self.is_room(from) || !self.is_room(to) {
+            return None;
+        }
+        let path = self.search_from(from, Some(to)).path_to(to)?;
+        Some(
+            path.windows(2)
+                .map(|pair| {
+                    self.doors(pair[0])
+                        .find(|&(_, next)| next == pair[1])
+                        .map(|(direction, _)| direction)
+                        .unwrap()
+                })
+                .collect(),
+        )
+    }
+
+    /// How many doors it takes to reach each room from the starting room.
+    fn distances(&self) -> Grid<Option<usize>> {
+        let visited = self.search();
+        let mut distances = Grid::new(self.rooms.bounds(), None);
+        for (coord, visit) in visited.iter() {
+            distances[coord] = visit.map(|v| v.cost);
+        }
+        distances
+    }
+
+    /// How many rooms are each number of doors away from the start.
+    fn histogram(&self) -> Vec<usize> {
+        let mut histogram = vec![];
+        for &distance in self.distances().values().flatten() {
+            if distance >= histogram.len() {
+                histogram.resize(distance + 1, 0);
             }
-            if doors.south {
-                let y_ = y + 1;
-                if !distances.contains_key(&(x, y_)) {
-                    distances.insert((x, y_), distance + 1);
-                    stack.push((x, y_));
+            histogram[distance] += 1;
+        }
+        histogram
+    }
+
+    /// The most doors it takes to reach any room, and how many rooms take at
+    /// least `threshold` doors to reach.
+    fn furthest_rooms(&self, threshold: usize) -> (usize, usize) {
+        let histogram = self.histogram();
+        (histogram.len() - 1, histogram.iter().skip(threshold).sum())
+    }
+}
+
+impl Rooms {
+    /// A route regex that opens exactly these doors, naming every door only
+    /// once. It follows the tree of shortest routes from the starting room,
+    /// branching wherever that tree does, and steps through each door off the
+    /// tree from whichever of its rooms comes first in reading order.
+    fn to_regex(&self) -> Result<RoomRegex, String> {
+        let visited = self.search();
+        if let Some((coord, _)) = self
+            .rooms
+            .iter()
+            .find(|&(coord, doors)| visited[coord].is_none() && *doors != Doors::default())
+        {
+            return Err(format!(
+                "room at {},{} can't be reached from the start",
+                coord.x, coord.y
+            ));
+        }
+        Ok(RoomRegex::Group(
+            self.route_from(&visited, Coord::new(0, 0)),
+        ))
+    }
+
+    fn route_from(
+        &self,
+        visited: &Grid<Option<Visit<Coord, usize>>>,
+        start: Coord,
+    ) -> Vec<RoomRegex> {
+        let parent = |coord: Coord| visited[coord].and_then(|v| v.parent);
+        let mut route = vec![];
+        let mut coord = start;
+        loop {
+            // Each way on from here, and the room to carry on from if it is
+            // the first way into that room.
+            let ways = self
+                .doors(coord)
+                .filter_map(|(direction, next)| {
+                    if parent(next) == Some(coord) {
+                        Some((direction, Some(next)))
+                    } else if parent(coord) != Some(next) && coord < next {
+                        Some((direction, None))
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>();
+            match ways[..] {
+                [] => return route,
+                [(direction, next)] => {
+                    route.push(RoomRegex::Literal(direction));
+                    match next {
+                        Some(next) => coord = next,
+                        None => return route,
+                    }
                 }
-            }
-            if doors.west {
-                let x_ = x - 1;
-                if !distances.contains_key(&(x_, y)) {
-                    distances.insert((x_, y), distance + 1);
-                    stack.push((x_, y));
+                _ => {
+                    let branches = ways
+                        .into_iter()
+                        .map(|(direction, next)| {
+                            let mut branch = vec![RoomRegex::Literal(direction)];
+                            if let Some(next) = next {
+                                branch.extend(self.route_from(visited, next));
+                            }
+                            branch
+                        })
+                        .collect();
+                    route.push(RoomRegex::Or(branches));
+                    return route;
                 }
             }
         }
-        (
-            *distances.values().max().unwrap(),
-            distances.values().filter(|&&d| d >= 1000).count(),
-        )
     }
 }
 
 impl RoomRegex {
     fn to_rooms(&self) -> Rooms {
-        let mut rooms = HashMap::new();
-
-        fn walk(
-            rooms: &mut HashMap<(i32, i32), Doors>,
-            x: i32,
-            y: i32,
-            regex: &RoomRegex,
-        ) -> (i32, i32) {
+        let mut rooms = Grid::new(
+            Bounds::new(Coord::new(0, 0), Coord::new(0, 0)),
+            Doors::default(),
+        );
+
+        fn doors(rooms: &mut Grid<Doors>, coord: Coord) -> &mut Doors {
+            rooms.grow_to_include(coord, Doors::default());
+            &mut rooms[coord]
+        }
+
+        fn walk(rooms: &mut Grid<Doors>, coord: Coord, regex: &RoomRegex) -> Coord {
             match regex {
                 RoomRegex::Literal(Direction::North) => {
-                    rooms.entry((x, y)).or_default().north = true;
-                    rooms.entry((x, y - 1)).or_default().south = true;
-                    (x, y - 1)
+                    let next = coord.offset(0, -1);
+                    doors(rooms, coord).north = true;
+                    doors(rooms, next).south = true;
+                    next
                 }
                 RoomRegex::Literal(Direction::East) => {
-                    rooms.entry((x, y)).or_default().east = true;
-                    rooms.entry((x + 1, y)).or_default().west = true;
-                    (x + 1, y)
+                    let next = coord.offset(1, 0);
+                    doors(rooms, coord).east = true;
+                    doors(rooms, next).west = true;
+                    next
                 }
                 RoomRegex::Literal(Direction::South) => {
-                    rooms.entry((x, y)).or_default().south = true;
-                    rooms.entry((x, y + 1)).or_default().north = true;
-                    (x, y + 1)
+                    let next = coord.offset(0, 1);
+                    doors(rooms, coord).south = true;
+                    doors(rooms, next).north = true;
+                    next
                 }
                 RoomRegex::Literal(Direction::West) => {
-                    rooms.entry((x, y)).or_default().west = true;
-                    rooms.entry((x - 1, y)).or_default().east = true;
-                    (x - 1, y)
-                }
-                RoomRegex::Group(group) => {
-                    let mut x = x;
-                    let mut y = y;
-                    for r in group {
-                        let (x_, y_) = walk(rooms, x, y, r);
-                        x = x_;
-                        y = y_;
-                    }
-                    (x, y)
+                    let next = coord.offset(-1, 0);
+                    doors(rooms, coord).west = true;
+                    doors(rooms, next).east = true;
+                    next
                 }
+                RoomRegex::Group(group) => group.iter().fold(coord, |c, r| walk(rooms, c, r)),
                 RoomRegex::Or(group) => {
-                    let mut c = (x, y);
+                    let mut c = coord;
                     for r in group {
-                        c = walk(rooms, x, y, &RoomRegex::Group(r.clone()));
+                        c = walk(rooms, coord, &RoomRegex::Group(r.clone()));
                     }
                     c
                 }
             }
         }
 
-        walk(&mut rooms, 0, 0, self);
+        walk(&mut rooms, Coord::new(0, 0), self);
 
         Rooms { rooms }
     }
@@ -260,6 +483,7 @@ impl RoomRegex {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::days::random::Random;
 
     #[test]
     fn test_parse() {
@@ -297,11 +521,46 @@ mod tests {
                 ],
             ])]))
         );
+
+        // A group without alternatives inside an alternative carries on
+        // along that alternative, rather than being another one.
+        use Direction::*;
+        let regex = "^N(E|S(W))$".parse::<RoomRegex>().unwrap();
+        assert_eq!(
+            regex,
+            RoomRegex::Group(vec![
+                RoomRegex::Literal(North),
+                RoomRegex::Or(vec![
+                    vec![RoomRegex::Literal(East)],
+                    vec![
+                        RoomRegex::Literal(South),
+                        RoomRegex::Group(vec![RoomRegex::Literal(West)])
+                    ],
+                ]),
+            ])
+        );
+        let rooms = regex.to_rooms();
+        assert_eq!(
+            rooms.map().render(|_, &c| c),
+            "#######\n#.#.|.#\n###-###\n#.|X#.#\n#######\n"
+        );
+        let distances = rooms.distances();
+        assert_eq!(distances[Coord::new(-1, 0)], Some(1));
+        assert_eq!(distances[Coord::new(-1, -1)], None);
+
+        assert_eq!(
+            "^N(E|S$".parse::<RoomRegex>(),
+            Err("unmatched (".to_owned())
+        );
+        assert_eq!("^NE)S$".parse::<RoomRegex>(), Err("unmatched )".to_owned()));
     }
 
     #[test]
     fn example() {
-        let instance = Instance { verbose: true };
+        let instance = Instance {
+            verbose: true,
+            ..Instance::default()
+        };
         let examples = vec![
             ("^WNE$", 3),
             ("^ENWWW(NEEE|SSE(EE|N))$", 10),
@@ -321,4 +580,209 @@ mod tests {
             assert_eq!(instance.run(input), Ok(expected));
         }
     }
+
+    #[test]
+    fn test_loop() {
+        // Walking once around the loop reaches the room east of the start
+        // last, but its door to the start makes it only one door away.
+        let rooms = "^NNNEEESSSWWW$".parse::<RoomRegex>().unwrap().to_rooms();
+        let distances = rooms.distances();
+        assert_eq!(distances[Coord::new(1, 0)], Some(1));
+        assert_eq!(distances[Coord::new(3, -3)], Some(6));
+        assert_eq!(rooms.histogram(), vec![1, 2, 2, 2, 2, 2, 1]);
+        assert_eq!(rooms.furthest_rooms(5), (6, 3));
+    }
+
+    #[test]
+    fn test_map() {
+        let rooms = "^ENWWW(NEEE|SSE(EE|N))$"
+            .parse::<RoomRegex>()
+            .unwrap()
+            .to_rooms();
+        let map = rooms.map().render(|_, &c| c);
+        assert_eq!(
+            map,
+            "#########
+#.|.|.|.#
+#-#######
+#.|.|.|.#
+#-#####-#
+#.#.#X|.#
+#-#-#####
+#.|.|.|.#
+#########
+"
+        );
+
+        let image = rooms.image();
+        assert_eq!(image.pixel(0, 0), [0x40, 0x40, 0x40]);
+        assert_eq!(image.pixel(5, 5), [0xff, 0xff, 0xff]);
+        assert_eq!(image.pixel(9, 5), [0xc0, 0xc0, 0xc0]);
+        assert_eq!(image.pixel(21, 21), [0x20, 0xa0, 0x20]);
+        // The room ten doors away is the bottom right one.
+        assert_eq!(image.pixel(29, 29), [0xd0, 0x20, 0x20]);
+        assert_eq!(image.pixel(29, 5), [0xff, 0xff, 0xff]);
+    }
+
+    /// A random route with brackets nested up to `depth` deep.
+    fn random_route(random: &mut Random, depth: usize) -> String {
+        let mut route = String::new();
+        for _ in 0..random.below(6) {
+            if depth > 0 && random.below(4) == 0 {
+                let branches = (0..2 + random.below(2))
+                    .map(|_| random_route(random, depth - 1))
+                    .collect::<Vec<_>>();
+                route += &format!("({})", branches.join("|"));
+            } else {
+                route.push(['N', 'E', 'S', 'W'][random.below(4)]);
+            }
+        }
+        route
+    }
+
+    #[test]
+    fn test_display() {
+        for regex in [
+            "^WNE$",
+            "^ENWWW(NEEE|SSE(EE|N))$",
+            "^ENNWSWW(NEWS|)SSSEEN(WNSE|)EE(SWEN|)NNN$",
+            "^ESSWWN(E|NNENN(EESS(WNSE|)SSS|WWWSSSSE(SW|NNNE)))$",
+        ] {
+            assert_eq!(regex.parse::<RoomRegex>().unwrap().to_string(), regex);
+        }
+        // Brackets without alternatives change nothing.
+        let regex = "^N(E(S)W|(N))$".parse::<RoomRegex>().unwrap();
+        assert_eq!(regex.to_string(), "^N(ESW|N)$");
+        assert_eq!(
+            regex.to_string().parse::<RoomRegex>().unwrap().to_rooms(),
+            regex.to_rooms()
+        );
+        assert!("^N(E|S$".parse::<RoomRegex>().is_err());
+        assert!("^N)$".parse::<RoomRegex>().is_err());
+
+        let mut random = Random::new(20);
+        for _ in 0..200 {
+            let regex = format!("^{}$", random_route(&mut random, 3))
+                .parse::<RoomRegex>()
+                .unwrap();
+            let normalized = regex.to_string();
+            let reparsed = normalized.parse::<RoomRegex>().unwrap();
+            assert_eq!(reparsed.to_string(), normalized);
+            assert_eq!(reparsed.to_rooms(), regex.to_rooms());
+        }
+    }
+
+    #[test]
+    fn test_minimal_regex() {
+        let rooms = "^ENWWW(NEEE|SSE(EE|N))$"
+            .parse::<RoomRegex>()
+            .unwrap()
+            .to_rooms();
+        assert_eq!(
+            rooms.to_regex().unwrap().to_string(),
+            "^ENWWW(NEEE|SSE(N|EE))$"
+        );
+
+        // The door off the tree is taken from the room above it.
+        let rooms = "^NESW$".parse::<RoomRegex>().unwrap().to_rooms();
+        assert_eq!(rooms.to_regex().unwrap().to_string(), "^(NES|E)$");
+
+        let mut random = Random::new(49);
+        for _ in 0..200 {
+            let regex = format!("^{}$", random_route(&mut random, 4))
+                .parse::<RoomRegex>()
+                .unwrap();
+            let rooms = regex.to_rooms();
+            let minimal = rooms.to_regex().unwrap().to_string();
+            assert_eq!(minimal.parse::<RoomRegex>().unwrap().to_rooms(), rooms);
+            let doors = rooms
+                .rooms
+                .values()
+                .map(|d| {
+                    [d.north, d.east, d.south, d.west]
+                        .iter()
+                        .filter(|&&d| d)
+                        .count()
+                })
+                .sum::<usize>();
+            let steps = minimal.chars().filter(|c| "NESW".contains(*c)).count();
+            assert_eq!(2 * steps, doors);
+        }
+
+        let mut rooms = "^NS$".parse::<RoomRegex>().unwrap().to_rooms();
+        rooms
+            .rooms
+            .grow_to_include(Coord::new(2, 0), Doors::default());
+        rooms.rooms[Coord::new(1, 0)].east = true;
+        rooms.rooms[Coord::new(2, 0)].west = true;
+        assert_eq!(
+            rooms.to_regex(),
+            Err("room at 1,0 can't be reached from the start".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_routes() {
+        let rooms = "^ESSWWN(E|NNENN(EESS(WNSE|)SSS|WWWSSSSE(SW|NNNE)))$"
+            .parse::<RoomRegex>()
+            .unwrap()
+            .to_rooms();
+        let route = |from, to| {
+            rooms
+                .route(from, to)
+                .map(|route| route.iter().map(|d| d.symbol()).collect::<String>())
+        };
+        assert_eq!(
+            route(Coord::new(0, 0), Coord::new(0, 0)),
+            Some(String::new())
+        );
+        assert_eq!(
+            route(Coord::new(0, 0), Coord::new(0, 1)),
+            Some("ESSWWNE".to_owned())
+        );
+        assert_eq!(
+            route(Coord::new(0, 1), Coord::new(0, 0)),
+            Some("WSEENNW".to_owned())
+        );
+        assert_eq!(route(Coord::new(0, 0), Coord::new(9, 9)), None);
+
+        let histogram = rooms.histogram();
+        assert_eq!(histogram.len(), 24);
+        assert_eq!(histogram[..4], [1, 1, 1, 1]);
+        assert_eq!(
+            histogram.iter().sum::<usize>(),
+            rooms.distances().values().flatten().count()
+        );
+        assert_eq!(rooms.furthest_rooms(1000), (23, 0));
+        assert_eq!(rooms.furthest_rooms(23), (23, histogram[23]));
+        assert_eq!(rooms.furthest_rooms(0).1, histogram.iter().sum::<usize>());
+
+        let instance = Instance {
+            threshold: 20,
+            ..Instance::default()
+        };
+        let expected = histogram[20..].iter().sum::<usize>().to_string();
+        assert_eq!(
+            instance
+                .run("^ESSWWN(E|NNENN(EESS(WNSE|)SSS|WWWSSSSE(SW|NNNE)))$")
+                .map(|result| result.part2),
+            Ok(Some(expected))
+        );
+
+        // 1,0 is inside the map but has no doors, so it isn't a room.
+        let rooms = "^NEES$".parse::<RoomRegex>().unwrap().to_rooms();
+        assert_eq!(rooms.route(Coord::new(1, 0), Coord::new(1, 0)), None);
+        assert_eq!(rooms.route(Coord::new(0, 0), Coord::new(1, 0)), None);
+        assert_eq!(
+            rooms
+                .route(Coord::new(0, 0), Coord::new(2, 0))
+                .map(|r| r.len()),
+            Some(4)
+        );
+        let rooms = "^$".parse::<RoomRegex>().unwrap().to_rooms();
+        assert_eq!(
+            rooms.route(Coord::new(0, 0), Coord::new(0, 0)),
+            Some(vec![])
+        );
+    }
 }
@@ -2,23 +2,47 @@ use std::{collections::HashMap, str::FromStr};
 
 use itertools::Itertools;
 
-use super::day::*;
+use super::{
+    day::*,
+    grid::{Bounds, Coord, Grid},
+    search::{astar, Control, Visited},
+};
 
-pub struct Instance;
+#[derive(Default)]
+pub struct Instance {
+    verbose: bool,
+}
 
 impl Day for Instance {
     fn run(&self, input: &str) -> Result<DayResult, String> {
         let mut area = input.parse::<Area>()?;
         let part1 = area.risk_level().to_string();
-        let part2 = Some(area.traverse().to_string());
+        let route = area.traverse();
+        if self.verbose {
+            print!("{}", area.render_route(&route));
+            println!(
+                "Moving: {} minutes, switching tools: {} minutes ({} switches)",
+                route.moving_minutes(),
+                route.switching_minutes(),
+                route.switches()
+            );
+        }
+        let part2 = Some(route.minutes().to_string());
         Ok(DayResult { part1, part2 })
     }
 }
 
+/// Geologic indices wrap around this modulus.
+const MODULUS: i32 = 20183;
+
 struct Area {
     depth: i32,
-    target: (i32, i32),
-    geologic_indices: HashMap<(i32, i32), i32>,
+    target: Coord,
+    /// Routes never need to leave these bounds, see `Area::new`.
+    search_bounds: Bounds,
+    /// The erosion values `(geologic index + depth) % MODULUS` of a rectangle
+    /// from the mouth, grown as the search reaches further.
+    erosion: Grid<i32>,
 }
 
 impl FromStr for Area {
@@ -37,174 +61,210 @@ impl FromStr for Area {
             return Err(format!("invalid area: {}", s));
         }
         if let (Some(&depth), Some(&x), Some(&y)) =
-            (parts[0].get(0), parts[1].get(0), parts[1].get(1))
+            (parts[0].first(), parts[1].first(), parts[1].get(1))
         {
-            return Ok(Area {
-                depth,
-                target: (x, y),
-                geologic_indices: HashMap::new(),
-            });
+            if depth < 0 || x < 0 || y < 0 {
+                return Err(format!("invalid area: {}", s));
+            }
+            Ok(Area::new(depth, Coord::new(x, y)))
         } else {
-            return Err(format!("invalid area: {}", s));
+            Err(format!("invalid area: {}", s))
         }
     }
 }
 
 impl Area {
-    fn geologic_index(&mut self, x: i32, y: i32) -> i32 {
-        let index = if (x, y) == (0, 0) || (x, y) == self.target {
-            0
-        } else if y == 0 {
-            x * 16807
-        } else if x == 0 {
-            y * 48271
-        } else if let Some(v) = self.geologic_indices.get(&(x, y)) {
-            *v
-        } else {
-            let v = self.geologic_index(x - 1, y) * self.geologic_index(x, y - 1);
-
-            self.geologic_indices.insert((x, y), v % 20183);
-
-            v
+    /// Walking straight to the target and switching tools at every step takes
+    /// at most `8 * (x + y) + 7` minutes. Any route going further right than
+    /// half of that plus `x` (or further down than half of it plus `y`) has to
+    /// walk there and back, which takes longer, so the search is bounded there.
+    fn new(depth: i32, target: Coord) -> Self {
+        let limit = 8 * (target.x + target.y) + 7;
+        let search_bounds = Bounds::new(
+            Coord::new(0, 0),
+            Coord::new((limit + target.x) / 2, (limit + target.y) / 2),
+        );
+        let mut area = Area {
+            depth,
+            target,
+            search_bounds,
+            erosion: Grid::new(Bounds::new(Coord::new(0, 0), Coord::new(-1, -1)), 0),
         };
+        area.grow_to_include(target);
+        area
+    }
 
-        (index + self.depth) % 20183
+    /// Extends the erosion values to cover `coord`, at least doubling the
+    /// width or height that needs to grow so repeated growth stays cheap. New
+    /// values are computed in reading order, so the values above and to the
+    /// left of each one are already known.
+    fn grow_to_include(&mut self, coord: Coord) {
+        let old = self.erosion.bounds();
+        let grow = |wanted: i32, current: i32, limit: i32| {
+            if wanted > current {
+                wanted.max(current * 2 + 1).min(limit)
+            } else {
+                current
+            }
+        };
+        let max = Coord::new(
+            grow(coord.x, old.max.x, self.search_bounds.max.x),
+            grow(coord.y, old.max.y, self.search_bounds.max.y),
+        );
+        let mut erosion = Grid::new(Bounds::new(Coord::new(0, 0), max), 0);
+        for c in erosion.bounds().coords() {
+            erosion[c] = if old.contains(c) {
+                self.erosion[c]
+            } else {
+                let index = if c == Coord::new(0, 0) || c == self.target {
+                    0
+                } else if c.y == 0 {
+                    c.x * 16807
+                } else if c.x == 0 {
+                    c.y * 48271
+                } else {
+                    erosion[c.offset(-1, 0)] * erosion[c.offset(0, -1)]
+                };
+                (index + self.depth) % MODULUS
+            };
+        }
+        self.erosion = erosion;
     }
 
-    fn erosion_level(&mut self, x: i32, y: i32) -> i32 {
-        self.geologic_index(x, y) % 3
+    /// The region type at `coord`: 0 for rocky, 1 for wet and 2 for narrow.
+    fn erosion_level(&mut self, coord: Coord) -> i32 {
+        if !self.erosion.contains(coord) {
+            self.grow_to_include(coord);
+        }
+        self.erosion[coord] % 3
     }
 
     fn risk_level(&mut self) -> i32 {
-        (0..=self.target.0)
-            .cartesian_product(0..=self.target.1)
-            .map(|(x, y)| self.erosion_level(x, y))
+        Bounds::new(Coord::new(0, 0), self.target)
+            .coords()
+            .map(|c| self.erosion_level(c))
             .sum()
     }
 
-    fn traverse(&mut self) -> i32 {
-        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-        struct State {
-            position: (i32, i32),
-            tool: Tool,
-        }
-
-        let mut visited = HashMap::new();
-        let mut to_visit = HashMap::new();
-        to_visit.insert(
-            0,
-            vec![State {
-                position: (0, 0),
+    /// The quickest route to the target holding the torch, where moving takes
+    /// a minute and switching tools takes seven.
+    fn traverse(&mut self) -> Route {
+        let target = State {
+            position: self.target,
+            tool: Tool::Torch,
+        };
+        let search = astar(
+            [State {
+                position: Coord::new(0, 0),
                 tool: Tool::Torch,
             }],
+            HashMap::new(),
+            |state| self.next_states(state),
+            |state| {
+                let Coord { x, y } = state.position;
+                let switch = if state.tool == Tool::Torch { 0 } else { 7 };
+                (x - target.position.x).abs() + (y - target.position.y).abs() + switch
+            },
+            |state, _| {
+                if state == target {
+                    Control::Stop
+                } else {
+                    Control::Continue
+                }
+            },
         );
+        let states = search
+            .stopped_at
+            .and_then(|state| search.visited.path_to(state))
+            .expect("the target is always reachable");
+        Route { states }
+    }
 
-        fn process_state(
-            state: State,
-            minutes: i32,
-            visited: &mut HashMap<State, i32>,
-            to_visit: &mut HashMap<i32, Vec<State>>,
-            area: &mut Area,
-        ) -> Option<i32> {
-            if state.position == area.target && state.tool == Tool::Torch {
-                return Some(minutes);
+    fn next_states(&mut self, state: State) -> Vec<(State, i32)> {
+        let erosion = self.erosion_level(state.position);
+        let mut next = Tool::ALL
+            .iter()
+            .filter(|&&tool| tool != state.tool && tool.is_valid(erosion))
+            .map(|&tool| (State { tool, ..state }, 7))
+            .collect_vec();
+        // Down, right, up and left, which decides between routes that take
+        // equally long.
+        for (dx, dy) in [(0, 1), (1, 0), (0, -1), (-1, 0)] {
+            let position = state.position.offset(dx, dy);
+            if !self.search_bounds.contains(position) {
+                continue;
             }
-
-            if let Some(&visited_minutes) = visited.get(&state) {
-                if visited_minutes <= minutes {
-                    return None;
-                }
+            if state.tool.is_valid(self.erosion_level(position)) {
+                next.push((State { position, ..state }, 1));
             }
-
-            visited.insert(state.clone(), minutes);
-
-            let (x, y) = state.position;
-            let current_erosion = area.erosion_level(x, y);
-
-            for &(dx, dy) in &[(0, 1), (1, 0), (0, -1), (-1, 0)] {
-                let new_position = (x + dx, y + dy);
-                if new_position.0 < 0 || new_position.1 < 0 {
-                    continue;
-                }
-                let new_erosion = area.erosion_level(new_position.0, new_position.1);
-
-                add_new_states(
-                    current_erosion,
-                    new_erosion,
-                    state.tool,
-                    new_position,
-                    minutes,
-                    visited,
-                    to_visit,
-                );
-            }
-            return None;
         }
+        next
+    }
 
-        fn add_new_states(
-            current_erosion: i32,
-            new_erosion: i32,
-            current_tool: Tool,
-            new_position: (i32, i32),
-            minutes: i32,
-            visited: &HashMap<State, i32>,
-            to_visit: &mut HashMap<i32, Vec<State>>,
-        ) {
-            for &tool in &[Tool::Torch, Tool::ClimbingGear, Tool::Neither] {
-                if !tool.is_valid(current_erosion) {
-                    continue;
-                }
-                if !tool.is_valid(new_erosion) {
-                    continue;
-                }
-
-                let minutes = minutes + if current_tool == tool { 1 } else { 8 };
-                if let Some(&visited_minutes) = visited.get(&State {
-                    position: new_position,
-                    tool,
-                }) {
-                    if visited_minutes <= minutes {
-                        continue;
-                    }
-                }
-                to_visit
-                    .entry(minutes)
-                    .and_modify(|state| {
-                        state.push(State {
-                            position: new_position,
-                            tool,
-                        })
-                    })
-                    .or_insert_with(|| {
-                        vec![State {
-                            position: new_position,
-                            tool,
-                        }]
-                    });
-            }
+    /// The cave from the mouth to past the target and the route, drawn with
+    /// `.` for rocky, `=` for wet and `|` for narrow regions. The route shows
+    /// the tool held as `t`orch, `c`limbing gear or `n`either, and `*` where
+    /// the tool was switched.
+    fn render_route(&mut self, route: &Route) -> String {
+        let bounds = route.states.iter().map(|state| state.position).fold(
+            Bounds::new(Coord::new(0, 0), self.target),
+            Bounds::including,
+        );
+        let mut map = Grid::new(bounds, ' ');
+        for coord in bounds.coords() {
+            map[coord] = match self.erosion_level(coord) {
+                0 => '.',
+                1 => '=',
+                _ => '|',
+            };
+        }
+        for (from, to) in route.states.iter().tuple_windows() {
+            map[to.position] = if from.tool == to.tool {
+                to.tool.symbol()
+            } else {
+                '*'
+            };
         }
+        map[Coord::new(0, 0)] = 'M';
+        map[self.target] = 'T';
+        map.render(|_, &c| c)
+    }
+}
+
+/// The states passed through on the way to the target, starting at the mouth.
+#[derive(Debug)]
+struct Route {
+    states: Vec<State>,
+}
 
-        while !to_visit.is_empty() {
-            let (minutes, states) = to_visit
-                .iter()
-                .min_by_key(|(minutes, _)| minutes.to_owned())
-                .unwrap();
+impl Route {
+    fn switches(&self) -> usize {
+        self.states
+            .iter()
+            .tuple_windows()
+            .filter(|(from, to)| from.tool != to.tool)
+            .count()
+    }
 
-            let states = states.to_owned();
-            let minutes = *minutes;
-            to_visit.remove(&minutes);
+    fn moving_minutes(&self) -> usize {
+        self.states.len() - 1 - self.switches()
+    }
 
-            for state in states {
-                if let Some(m) = process_state(state, minutes, &mut visited, &mut to_visit, self) {
-                    return m;
-                }
-            }
-        }
+    fn switching_minutes(&self) -> usize {
+        self.switches() * 7
+    }
 
-        unreachable!()
+    fn minutes(&self) -> usize {
+        self.moving_minutes() + self.switching_minutes()
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct State {
+    position: Coord,
+    tool: Tool,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum Tool {
     Torch,
@@ -213,6 +273,16 @@ enum Tool {
 }
 
 impl Tool {
+    const ALL: [Tool; 3] = [Tool::Torch, Tool::ClimbingGear, Tool::Neither];
+
+    fn symbol(&self) -> char {
+        match self {
+            Tool::Torch => 't',
+            Tool::ClimbingGear => 'c',
+            Tool::Neither => 'n',
+        }
+    }
+
     fn is_valid(&self, erosion: i32) -> bool {
         match self {
             Tool::Torch => erosion != 1,
@@ -228,7 +298,7 @@ mod tests {
 
     #[test]
     fn example() {
-        let instance = Instance;
+        let instance = Instance::default();
         let input = "depth: 510
 target: 10,10
 ";
@@ -238,4 +308,39 @@ target: 10,10
         };
         assert_eq!(instance.run(input), Ok(expected));
     }
+
+    #[test]
+    fn test_route() {
+        let mut area = "depth: 510\ntarget: 10,10".parse::<Area>().unwrap();
+        let route = area.traverse();
+        assert_eq!(route.minutes(), 45);
+        assert_eq!(route.switches(), 3);
+        assert_eq!(route.moving_minutes(), 24);
+        assert_eq!(route.states.first().unwrap().position, Coord::new(0, 0));
+        assert_eq!(route.states.last().unwrap().position, Coord::new(10, 10));
+
+        let map = area.render_route(&route);
+        let lines = map.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 13);
+        assert_eq!(lines[0], "M=.|=.|.|=.");
+        assert_eq!(lines[1], "t*nn*|||..|");
+        assert_eq!(lines[10], ".===|c|===T");
+        assert_eq!(lines[12], "=.=|=.cccc*");
+    }
+
+    #[test]
+    fn test_erosion_cache() {
+        let mut area = Area::new(510, Coord::new(10, 10));
+        assert_eq!(area.erosion[Coord::new(1, 1)], 1805);
+        assert_eq!(area.erosion[Coord::new(10, 10)], 510);
+
+        let before = area.erosion.clone();
+        assert_eq!(area.erosion_level(Coord::new(30, 2)), 1);
+        assert_eq!(area.erosion.bounds().max, Coord::new(30, 10));
+        assert!(before.iter().all(|(c, &v)| area.erosion[c] == v));
+
+        assert_eq!(area.search_bounds.max, Coord::new(88, 88));
+        area.erosion_level(Coord::new(40, 3));
+        assert_eq!(area.erosion.bounds().max, Coord::new(61, 10));
+    }
 }
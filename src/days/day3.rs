@@ -1,6 +1,9 @@
-use std::{collections::HashMap, str::FromStr};
+use std::str::FromStr;
 
-use super::day::*;
+use super::{
+    day::*,
+    grid::{Bounds, Coord, Grid},
+};
 
 pub struct Instance;
 
@@ -13,9 +16,7 @@ impl Day for Instance {
             .map(|line| line.parse::<Claim>())
             .collect::<Result<Vec<_>, _>>()?;
 
-        let mut fabric = Fabric {
-            fabric: HashMap::new(),
-        };
+        let mut fabric = Fabric::new(&claims);
 
         for claim in &claims {
             fabric.add_claim(claim);
@@ -75,38 +76,45 @@ impl FromStr for Claim {
     }
 }
 
+impl Claim {
+    fn bounds(&self) -> Bounds {
+        Bounds::new(
+            Coord::new(self.x, self.y),
+            Coord::new(self.x + self.width - 1, self.y + self.height - 1),
+        )
+    }
+}
+
+/// How many claims cover each square inch.
 struct Fabric {
-    fabric: HashMap<(i32, i32), Vec<Id>>,
+    fabric: Grid<u16>,
 }
 
 impl Fabric {
+    fn new(claims: &[Claim]) -> Self {
+        let bounds =
+            Bounds::enclosing(claims.iter().flat_map(|c| [c.bounds().min, c.bounds().max]))
+                .unwrap_or(Bounds::new(Coord::new(0, 0), Coord::new(-1, -1)));
+        Self {
+            fabric: Grid::new(bounds, 0),
+        }
+    }
+
     fn add_claim(&mut self, claim: &Claim) {
-        for x in claim.x..claim.x + claim.width {
-            for y in claim.y..claim.y + claim.height {
-                self.fabric
-                    .entry((x, y))
-                    .or_insert_with(Vec::new)
-                    .push(claim.id);
-            }
+        for coord in claim.bounds().coords() {
+            self.fabric[coord] += 1;
         }
     }
 
     fn count_overlaps(&self) -> usize {
-        self.fabric.values().filter(|v| v.len() > 1).count()
+        self.fabric.values().filter(|&&v| v > 1).count()
     }
 
     fn find_non_overlapping_claim(&self, claims: &[Claim]) -> Option<Id> {
-        for claim in claims {
-            let overlaps = self
-                .fabric
-                .iter()
-                .filter(|(_, v)| v.len() > 1)
-                .any(|(_, v)| v.contains(&claim.id));
-            if !overlaps {
-                return Some(claim.id);
-            }
-        }
-        None
+        claims
+            .iter()
+            .find(|claim| claim.bounds().coords().all(|c| self.fabric[c] == 1))
+            .map(|claim| claim.id)
     }
 }
 
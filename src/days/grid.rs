@@ -0,0 +1,299 @@
+use std::ops::{Index, IndexMut};
+
+use serde::Serialize;
+
+/// A position on a grid, with `y` growing downwards. Coordinates are ordered
+/// in reading order: top to bottom, then left to right.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord, Default, Serialize)]
+pub struct Coord {
+    pub y: i32,
+    pub x: i32,
+}
+
+impl Coord {
+    pub const fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    pub fn offset(self, dx: i32, dy: i32) -> Self {
+        Self::new(self.x + dx, self.y + dy)
+    }
+
+    /// The four orthogonal neighbours, in reading order.
+    pub fn neighbours4(self) -> [Coord; 4] {
+        [
+            self.offset(0, -1),
+            self.offset(-1, 0),
+            self.offset(1, 0),
+            self.offset(0, 1),
+        ]
+    }
+
+    /// All eight surrounding coordinates, in reading order.
+    pub fn neighbours8(self) -> [Coord; 8] {
+        [
+            self.offset(-1, -1),
+            self.offset(0, -1),
+            self.offset(1, -1),
+            self.offset(-1, 0),
+            self.offset(1, 0),
+            self.offset(-1, 1),
+            self.offset(0, 1),
+            self.offset(1, 1),
+        ]
+    }
+}
+
+/// An inclusive rectangle of coordinates.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Bounds {
+    pub min: Coord,
+    pub max: Coord,
+}
+
+impl Bounds {
+    pub fn new(min: Coord, max: Coord) -> Self {
+        Self { min, max }
+    }
+
+    /// The smallest bounds containing every coordinate, if there are any.
+    pub fn enclosing(coords: impl IntoIterator<Item = Coord>) -> Option<Self> {
+        let mut coords = coords.into_iter();
+        let first = coords.next()?;
+        Some(coords.fold(Self::new(first, first), Self::including))
+    }
+
+    pub fn including(self, coord: Coord) -> Self {
+        Self::new(
+            Coord::new(self.min.x.min(coord.x), self.min.y.min(coord.y)),
+            Coord::new(self.max.x.max(coord.x), self.max.y.max(coord.y)),
+        )
+    }
+
+    pub fn width(&self) -> usize {
+        (self.max.x - self.min.x + 1).max(0) as usize
+    }
+
+    pub fn height(&self) -> usize {
+        (self.max.y - self.min.y + 1).max(0) as usize
+    }
+
+    pub fn contains(&self, coord: Coord) -> bool {
+        coord.x >= self.min.x
+            && coord.x <= self.max.x
+            && coord.y >= self.min.y
+            && coord.y <= self.max.y
+    }
+
+    /// Every coordinate in the bounds, in reading order.
+    pub fn coords(&self) -> impl Iterator<Item = Coord> {
+        let Bounds { min, max } = *self;
+        (min.y..=max.y).flat_map(move |y| (min.x..=max.x).map(move |x| Coord::new(x, y)))
+    }
+}
+
+/// A dense rectangular grid of cells covering some `Bounds`, which need not
+/// start at the origin.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Grid<T> {
+    bounds: Bounds,
+    cells: Vec<T>,
+}
+
+impl<T> Grid<T> {
+    pub fn new(bounds: Bounds, fill: T) -> Self
+    where
+        T: Clone,
+    {
+        Self {
+            bounds,
+            cells: vec![fill; bounds.width() * bounds.height()],
+        }
+    }
+
+    /// Parses one cell per character, with the top left character at (0, 0).
+    /// Trailing whitespace is ignored and short lines are padded with spaces.
+    pub fn parse(
+        s: &str,
+        mut f: impl FnMut(Coord, char) -> Result<T, String>,
+    ) -> Result<Self, String> {
+        let width = s
+            .lines()
+            .map(|line| line.trim_end().chars().count())
+            .max()
+            .unwrap_or(0);
+        let height = s.lines().count();
+        let mut cells = Vec::with_capacity(width * height);
+        for (y, line) in s.lines().enumerate() {
+            let mut chars = line.trim_end().chars();
+            for x in 0..width {
+                let c = chars.next().unwrap_or(' ');
+                cells.push(f(Coord::new(x as i32, y as i32), c)?);
+            }
+        }
+        let bounds = Bounds::new(
+            Coord::new(0, 0),
+            Coord::new(width as i32 - 1, height as i32 - 1),
+        );
+        Ok(Self { bounds, cells })
+    }
+
+    pub fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    pub fn width(&self) -> usize {
+        self.bounds.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.bounds.height()
+    }
+
+    pub fn contains(&self, coord: Coord) -> bool {
+        self.bounds.contains(coord)
+    }
+
+    fn index_of(&self, coord: Coord) -> Option<usize> {
+        if self.contains(coord) {
+            let x = (coord.x - self.bounds.min.x) as usize;
+            let y = (coord.y - self.bounds.min.y) as usize;
+            Some(y * self.width() + x)
+        } else {
+            None
+        }
+    }
+
+    pub fn get(&self, coord: Coord) -> Option<&T> {
+        self.index_of(coord).map(|i| &self.cells[i])
+    }
+
+    /// Every cell with its coordinate, in reading order.
+    pub fn iter(&self) -> impl Iterator<Item = (Coord, &T)> {
+        self.bounds.coords().zip(self.cells.iter())
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.cells.iter()
+    }
+
+    /// The orthogonal neighbours inside the grid, in reading order.
+    pub fn neighbours4(&self, coord: Coord) -> impl Iterator<Item = Coord> + '_ {
+        coord
+            .neighbours4()
+            .into_iter()
+            .filter(|&c| self.contains(c))
+    }
+
+    /// The orthogonal and diagonal neighbours inside the grid, in reading order.
+    pub fn neighbours8(&self, coord: Coord) -> impl Iterator<Item = Coord> + '_ {
+        coord
+            .neighbours8()
+            .into_iter()
+            .filter(|&c| self.contains(c))
+    }
+
+    /// Draws the grid one character per cell, with a newline after every row.
+    pub fn render(&self, mut f: impl FnMut(Coord, &T) -> char) -> String {
+        let mut s = String::with_capacity((self.width() + 1) * self.height());
+        for (coord, cell) in self.iter() {
+            s.push(f(coord, cell));
+            if coord.x == self.bounds.max.x {
+                s.push('\n');
+            }
+        }
+        s
+    }
+
+    /// Enlarges the grid so that it contains `coord`, filling new cells with `fill`.
+    pub fn grow_to_include(&mut self, coord: Coord, fill: T)
+    where
+        T: Clone,
+    {
+        if self.contains(coord) {
+            return;
+        }
+        let bounds = if self.cells.is_empty() {
+            Bounds::new(coord, coord)
+        } else {
+            self.bounds.including(coord)
+        };
+        let mut grown = Grid::new(bounds, fill);
+        for (c, cell) in self.bounds.coords().zip(self.cells.drain(..)) {
+            grown[c] = cell;
+        }
+        *self = grown;
+    }
+}
+
+impl<T> Index<Coord> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, coord: Coord) -> &T {
+        let i = self
+            .index_of(coord)
+            .unwrap_or_else(|| panic!("{:?} is outside {:?}", coord, self.bounds));
+        &self.cells[i]
+    }
+}
+
+impl<T> IndexMut<Coord> for Grid<T> {
+    fn index_mut(&mut self, coord: Coord) -> &mut T {
+        let i = self
+            .index_of(coord)
+            .unwrap_or_else(|| panic!("{:?} is outside {:?}", coord, self.bounds));
+        &mut self.cells[i]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_render() {
+        let grid = Grid::parse("#.#  \n.#\n", |_, c| Ok(c == '#')).unwrap();
+        assert_eq!(grid.width(), 3);
+        assert_eq!(grid.height(), 2);
+        assert!(grid[Coord::new(2, 0)]);
+        assert!(!grid[Coord::new(2, 1)]);
+        assert_eq!(grid.get(Coord::new(3, 0)), None);
+        assert_eq!(
+            grid.render(|_, &wall| if wall { '#' } else { '.' }),
+            "#.#\n.#.\n"
+        );
+    }
+
+    #[test]
+    fn test_neighbours() {
+        let grid = Grid::new(Bounds::new(Coord::new(0, 0), Coord::new(2, 2)), ());
+        assert_eq!(
+            grid.neighbours4(Coord::new(0, 1)).collect::<Vec<_>>(),
+            vec![Coord::new(0, 0), Coord::new(1, 1), Coord::new(0, 2)]
+        );
+        assert_eq!(
+            grid.neighbours8(Coord::new(2, 2)).collect::<Vec<_>>(),
+            vec![Coord::new(1, 1), Coord::new(2, 1), Coord::new(1, 2)]
+        );
+        let mut sorted = Coord::new(1, 1).neighbours8().to_vec();
+        sorted.sort();
+        assert_eq!(sorted, Coord::new(1, 1).neighbours8().to_vec());
+    }
+
+    #[test]
+    fn test_grow_to_include() {
+        let mut grid = Grid::new(Bounds::new(Coord::new(0, 0), Coord::new(1, 0)), 0);
+        grid[Coord::new(1, 0)] = 1;
+        grid.grow_to_include(Coord::new(-1, 2), 0);
+        assert_eq!(
+            grid.bounds(),
+            Bounds::new(Coord::new(-1, 0), Coord::new(1, 2))
+        );
+        assert_eq!(grid[Coord::new(1, 0)], 1);
+        assert_eq!(grid.values().sum::<i32>(), 1);
+        assert_eq!(
+            grid.render(|_, &v| char::from_digit(v as u32, 10).unwrap()),
+            "001\n000\n000\n"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use std::{fs, path::Path};
+
+use super::grid::{Coord, Grid};
+
+pub type Rgb = [u8; 3];
+
+/// A picture made of RGB pixels, which can be saved as a binary PPM or an
+/// uncompressed PNG without any image libraries.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Rgb>,
+}
+
+impl Image {
+    /// Draws every cell of a grid as a square of `scale` by `scale` pixels.
+    pub fn from_grid<T>(
+        grid: &Grid<T>,
+        scale: usize,
+        mut colour: impl FnMut(Coord, &T) -> Rgb,
+    ) -> Self {
+        let width = grid.width() * scale;
+        let height = grid.height() * scale;
+        let mut pixels = vec![[0; 3]; width * height];
+        let min = grid.bounds().min;
+        for (coord, cell) in grid.iter() {
+            let rgb = colour(coord, cell);
+            let x = (coord.x - min.x) as usize * scale;
+            let y = (coord.y - min.y) as usize * scale;
+            for row in y..y + scale {
+                pixels[row * width + x..row * width + x + scale].fill(rgb);
+            }
+        }
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn pixel(&self, x: usize, y: usize) -> Rgb {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut ppm = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        ppm.extend(self.pixels.iter().flatten());
+        ppm
+    }
+
+    /// A PNG whose image data is stored in deflate blocks without any
+    /// compression, which keeps the encoder short at the cost of file size.
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity((3 * self.width + 1) * self.height);
+        for row in self.pixels.chunks(self.width.max(1)).take(self.height) {
+            // Each row starts with its filter type, and 0 is no filter.
+            raw.push(0);
+            raw.extend(row.iter().flatten());
+        }
+
+        let mut header = vec![];
+        header.extend((self.width as u32).to_be_bytes());
+        header.extend((self.height as u32).to_be_bytes());
+        // 8 bits per channel, RGB, then the default compression, filtering
+        // and no interlacing.
+        header.extend([8, 2, 0, 0, 0]);
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png_chunk(&mut png, b"IHDR", &header);
+        png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    /// Writes the image to `path`, as a PNG if it ends in `.png` and as a PPM
+    /// otherwise.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let bytes = if path.extension().is_some_and(|e| e == "png") {
+            self.to_png()
+        } else {
+            self.to_ppm()
+        };
+        fs::write(path, bytes).map_err(|e| format!("couldn't write {}: {}", path.display(), e))
+    }
+}
+
+fn png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+/// A zlib stream holding `data` in stored deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 0xffff;
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        zlib.push(u8::from(blocks.peek().is_none()));
+        let len = block.len() as u16;
+        zlib.extend(len.to_le_bytes());
+        zlib.extend((!len).to_le_bytes());
+        zlib.extend(block);
+    }
+    zlib.extend(adler32(data).to_be_bytes());
+    zlib
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % MOD;
+        b = (b + a) % MOD;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn test_encode() {
+        let grid = Grid::parse("ab\nba", |_, c| Ok(c)).unwrap();
+        let image = Image::from_grid(&grid, 2, |_, &c| match c {
+            'a' => [255, 0, 0],
+            _ => [0, 0, 255],
+        });
+        assert_eq!((image.width, image.height), (4, 4));
+        assert_eq!(image.pixel(1, 1), [255, 0, 0]);
+        assert_eq!(image.pixel(2, 1), [0, 0, 255]);
+        assert_eq!(image.pixel(3, 3), [255, 0, 0]);
+
+        let ppm = image.to_ppm();
+        assert!(ppm.starts_with(b"P6\n4 4\n255\n\xff\x00\x00\xff\x00\x00\x00\x00\xff"));
+        assert_eq!(ppm.len(), 11 + 4 * 4 * 3);
+
+        let png = image.to_png();
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR\x00\x00\x00\x04"));
+        assert!(png.ends_with(b"\x00\x00\x00\x00IEND\xae\x42\x60\x82"));
+        // The image data is four rows of a filter byte and 12 colour bytes.
+        let idat = png.windows(4).position(|w| w == b"IDAT").unwrap();
+        assert_eq!(&png[idat - 4..idat], &(2 + 5 + 52 + 4u32).to_be_bytes());
+        assert_eq!(&png[idat + 4..idat + 11], b"\x78\x01\x01\x34\x00\xcb\xff");
+    }
+}
@@ -28,4 +28,12 @@ pub mod day23;
 pub mod day24;
 pub mod day25;
 
+mod automaton;
+mod cycle;
+mod grid;
+mod image;
 mod instructions;
+mod playback;
+#[cfg(test)]
+mod random;
+mod search;
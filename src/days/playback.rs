@@ -0,0 +1,48 @@
+use std::{thread, time::Duration};
+
+/// Draws frames over each other in the terminal at a steady rate.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Playback {
+    delay: Duration,
+}
+
+impl Playback {
+    /// Fails unless `frames_per_second` is positive, and small enough that
+    /// the gap between frames is still positive.
+    pub fn new(frames_per_second: f64) -> Result<Self, String> {
+        let invalid = || format!("invalid frames per second: {}", frames_per_second);
+        if frames_per_second.is_nan() || frames_per_second <= 0.0 {
+            return Err(invalid());
+        }
+        let delay =
+            Duration::try_from_secs_f64(frames_per_second.recip()).map_err(|_| invalid())?;
+        Ok(Self { delay })
+    }
+
+    /// Clears the screen, draws `frame` from the top left and waits until the
+    /// next frame is due.
+    pub fn show(&self, frame: &str) {
+        print!("\x1b[2J\x1b[H{}", frame);
+        thread::sleep(self.delay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new() {
+        assert_eq!(
+            Playback::new(4.0).map(|p| p.delay),
+            Ok(Duration::from_millis(250))
+        );
+        assert_eq!(
+            Playback::new(f64::INFINITY).map(|p| p.delay),
+            Ok(Duration::ZERO)
+        );
+        for frames_per_second in [0.0, -1.0, f64::NAN, 1e-300] {
+            assert!(Playback::new(frames_per_second).is_err());
+        }
+    }
+}
@@ -0,0 +1,20 @@
+/// A xorshift generator, so tests can try many inputs without a dependency.
+pub struct Random(u64);
+
+impl Random {
+    /// `seed` must not be 0, or every value is 0.
+    pub fn new(seed: u64) -> Self {
+        Random(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
@@ -0,0 +1,332 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, VecDeque},
+    hash::Hash,
+    ops::Add,
+};
+
+use super::grid::{Coord, Grid};
+
+/// How a node was first reached: the cost of getting there and the node it
+/// was reached from, which is `None` for the starting nodes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Visit<N, C> {
+    pub cost: C,
+    pub parent: Option<N>,
+}
+
+/// Whether a search should carry on after visiting a node.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Control {
+    Continue,
+    Stop,
+}
+
+/// Where a search records the nodes it has visited. Any `HashMap` works, and a
+/// `Grid` of options is a faster choice when the nodes are grid coordinates.
+pub trait Visited<N: Copy, C: Copy> {
+    fn get(&self, node: N) -> Option<Visit<N, C>>;
+
+    fn insert(&mut self, node: N, visit: Visit<N, C>);
+
+    fn cost(&self, node: N) -> Option<C> {
+        self.get(node).map(|v| v.cost)
+    }
+
+    /// The nodes from a starting node up to and including `node`.
+    fn path_to(&self, node: N) -> Option<Vec<N>> {
+        let mut path = vec![node];
+        let mut visit = self.get(node)?;
+        while let Some(parent) = visit.parent {
+            path.push(parent);
+            visit = self.get(parent)?;
+        }
+        path.reverse();
+        Some(path)
+    }
+}
+
+impl<N: Copy + Hash + Eq, C: Copy> Visited<N, C> for HashMap<N, Visit<N, C>> {
+    fn get(&self, node: N) -> Option<Visit<N, C>> {
+        HashMap::get(self, &node).copied()
+    }
+
+    fn insert(&mut self, node: N, visit: Visit<N, C>) {
+        HashMap::insert(self, node, visit);
+    }
+}
+
+impl<C: Copy> Visited<Coord, C> for Grid<Option<Visit<Coord, C>>> {
+    fn get(&self, node: Coord) -> Option<Visit<Coord, C>> {
+        Grid::get(self, node).copied().flatten()
+    }
+
+    fn insert(&mut self, node: Coord, visit: Visit<Coord, C>) {
+        self[node] = Some(visit);
+    }
+}
+
+/// The visited nodes of a finished search, and the node it stopped at if
+/// `on_visit` asked it to.
+#[derive(Debug)]
+pub struct Search<N, M> {
+    pub visited: M,
+    pub stopped_at: Option<N>,
+}
+
+/// Breadth first search from all of `starts` at once, counting steps. The
+/// starts and each node's neighbours are explored in the order given, so a
+/// node's path leads back to the earliest start among those closest to it.
+/// `on_visit` is called as each node is first reached.
+pub fn bfs<N, I, M>(
+    starts: impl IntoIterator<Item = N>,
+    mut visited: M,
+    mut neighbours: impl FnMut(N) -> I,
+    mut on_visit: impl FnMut(N, Visit<N, usize>) -> Control,
+) -> Search<N, M>
+where
+    N: Copy,
+    I: IntoIterator<Item = N>,
+    M: Visited<N, usize>,
+{
+    let mut queue = VecDeque::new();
+    for start in starts {
+        if visited.get(start).is_some() {
+            continue;
+        }
+        let visit = Visit {
+            cost: 0,
+            parent: None,
+        };
+        visited.insert(start, visit);
+        queue.push_back(start);
+        if on_visit(start, visit) == Control::Stop {
+            return Search {
+                visited,
+                stopped_at: Some(start),
+            };
+        }
+    }
+
+    while let Some(node) = queue.pop_front() {
+        let cost = visited.cost(node).unwrap() + 1;
+        for next in neighbours(node) {
+            if visited.get(next).is_some() {
+                continue;
+            }
+            let visit = Visit {
+                cost,
+                parent: Some(node),
+            };
+            visited.insert(next, visit);
+            queue.push_back(next);
+            if on_visit(next, visit) == Control::Stop {
+                return Search {
+                    visited,
+                    stopped_at: Some(next),
+                };
+            }
+        }
+    }
+
+    Search {
+        visited,
+        stopped_at: None,
+    }
+}
+
+/// Cheapest first search over weighted edges. `on_visit` is called once each
+/// node's cheapest cost is known, so stopping at a goal gives its shortest path.
+#[cfg(test)]
+pub fn dijkstra<N, C, I, M>(
+    starts: impl IntoIterator<Item = N>,
+    visited: M,
+    neighbours: impl FnMut(N) -> I,
+    on_visit: impl FnMut(N, Visit<N, C>) -> Control,
+) -> Search<N, M>
+where
+    N: Copy,
+    C: Copy + Ord + Add<Output = C> + Default,
+    I: IntoIterator<Item = (N, C)>,
+    M: Visited<N, C>,
+{
+    astar(starts, visited, neighbours, |_| C::default(), on_visit)
+}
+
+/// Like `dijkstra`, but explores nodes in order of cost so far plus the
+/// `heuristic` estimate of the cost still to go. The heuristic must never
+/// overestimate, or shortest paths can be missed.
+pub fn astar<N, C, I, M>(
+    starts: impl IntoIterator<Item = N>,
+    mut visited: M,
+    mut neighbours: impl FnMut(N) -> I,
+    mut heuristic: impl FnMut(N) -> C,
+    mut on_visit: impl FnMut(N, Visit<N, C>) -> Control,
+) -> Search<N, M>
+where
+    N: Copy,
+    C: Copy + Ord + Add<Output = C> + Default,
+    I: IntoIterator<Item = (N, C)>,
+    M: Visited<N, C>,
+{
+    let mut queue = BinaryHeap::new();
+    for start in starts {
+        queue.push(Entry {
+            priority: heuristic(start),
+            node: start,
+            visit: Visit {
+                cost: C::default(),
+                parent: None,
+            },
+        });
+    }
+
+    while let Some(Entry { node, visit, .. }) = queue.pop() {
+        if visited.get(node).is_some() {
+            continue;
+        }
+        visited.insert(node, visit);
+        if on_visit(node, visit) == Control::Stop {
+            return Search {
+                visited,
+                stopped_at: Some(node),
+            };
+        }
+        for (next, step) in neighbours(node) {
+            if visited.get(next).is_some() {
+                continue;
+            }
+            let cost = visit.cost + step;
+            queue.push(Entry {
+                priority: cost + heuristic(next),
+                node: next,
+                visit: Visit {
+                    cost,
+                    parent: Some(node),
+                },
+            });
+        }
+    }
+
+    Search {
+        visited,
+        stopped_at: None,
+    }
+}
+
+/// A node waiting in the priority queue, ordered so the lowest priority pops first.
+struct Entry<N, C> {
+    priority: C,
+    node: N,
+    visit: Visit<N, C>,
+}
+
+impl<N, C: Ord> PartialEq for Entry<N, C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority
+    }
+}
+
+impl<N, C: Ord> Eq for Entry<N, C> {}
+
+impl<N, C: Ord> PartialOrd for Entry<N, C> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<N, C: Ord> Ord for Entry<N, C> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.priority.cmp(&self.priority)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn maze() -> Grid<bool> {
+        Grid::parse(
+            "#######
+#.....#
+#.###.#
+#.#...#
+#.#.###
+#...#.#
+#######",
+            |_, c| Ok(c == '#'),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_bfs() {
+        let maze = maze();
+        let search = bfs(
+            [Coord::new(1, 1)],
+            HashMap::new(),
+            |c| maze.neighbours4(c).filter(|&n| !maze[n]),
+            |_, _| Control::Continue,
+        );
+        assert_eq!(search.stopped_at, None);
+        assert_eq!(search.visited.cost(Coord::new(3, 3)), Some(8));
+        assert_eq!(search.visited.cost(Coord::new(5, 5)), None);
+        assert_eq!(
+            search.visited.path_to(Coord::new(1, 3)),
+            Some(vec![Coord::new(1, 1), Coord::new(1, 2), Coord::new(1, 3)])
+        );
+
+        let search = bfs(
+            [Coord::new(1, 1)],
+            Grid::new(maze.bounds(), None),
+            |c| maze.neighbours4(c).filter(|&n| !maze[n]),
+            |c, _| {
+                if c == Coord::new(5, 2) {
+                    Control::Stop
+                } else {
+                    Control::Continue
+                }
+            },
+        );
+        assert_eq!(search.stopped_at, Some(Coord::new(5, 2)));
+        assert_eq!(search.visited.cost(Coord::new(5, 2)), Some(5));
+        assert_eq!(search.visited.cost(Coord::new(3, 5)), None);
+    }
+
+    #[test]
+    fn test_weighted_search() {
+        let maze = maze();
+        let goal = Coord::new(3, 3);
+        // Moving down is twice as expensive as any other move.
+        let neighbours = |c: Coord| {
+            maze.neighbours4(c)
+                .filter(|&n| !maze[n])
+                .map(move |n| (n, if n.y > c.y { 2 } else { 1 }))
+                .collect::<Vec<_>>()
+        };
+        let stop_at_goal = |c, _| {
+            if c == goal {
+                Control::Stop
+            } else {
+                Control::Continue
+            }
+        };
+
+        let search = dijkstra([Coord::new(1, 1)], HashMap::new(), neighbours, stop_at_goal);
+        assert_eq!(search.stopped_at, Some(goal));
+        assert_eq!(search.visited.cost(goal), Some(10));
+
+        let search = astar(
+            [Coord::new(1, 1)],
+            HashMap::new(),
+            neighbours,
+            |c| (c.x - goal.x).abs() + (c.y - goal.y).abs(),
+            stop_at_goal,
+        );
+        assert_eq!(search.visited.cost(goal), Some(10));
+        assert_eq!(
+            search.visited.path_to(goal).map(|p| p[4]),
+            Some(Coord::new(5, 1))
+        );
+    }
+}
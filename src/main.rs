@@ -41,17 +41,17 @@ fn main() {
     programs.insert(9, Box::new(day9::Instance));
     programs.insert(10, Box::new(day10::Instance));
     programs.insert(11, Box::new(day11::Instance));
-    programs.insert(12, Box::new(day12::Instance));
-    programs.insert(13, Box::new(day13::Instance));
+    programs.insert(12, Box::new(day12::Instance::default()));
+    programs.insert(13, Box::new(day13::Instance::default()));
     programs.insert(14, Box::new(day14::Instance));
-    programs.insert(15, Box::new(day15::Instance));
+    programs.insert(15, Box::new(day15::Instance::default()));
     programs.insert(16, Box::new(day16::Instance));
     programs.insert(17, Box::new(day17::Instance::default()));
-    programs.insert(18, Box::new(day18::Instance));
+    programs.insert(18, Box::new(day18::Instance::default()));
     programs.insert(19, Box::new(day19::Instance));
     programs.insert(20, Box::new(day20::Instance::default()));
     programs.insert(21, Box::new(day21::Instance));
-    programs.insert(22, Box::new(day22::Instance));
+    programs.insert(22, Box::new(day22::Instance::default()));
     programs.insert(23, Box::new(day23::Instance));
     programs.insert(24, Box::new(day24::Instance));
     programs.insert(25, Box::new(day25::Instance));